@@ -1,7 +1,18 @@
 use leafwing_input_manager::Actionlike;
+use serde::{Deserialize, Serialize};
 
 #[derive(
-    Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug,
+    Actionlike,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Hash,
+    Debug,
+    Serialize,
+    Deserialize,
 )]
 pub enum PlatformerAction {
     Right,
@@ -14,6 +25,7 @@ pub enum PlatformerAction {
     Jump,
     Heal,
     Dash,
+    Attack,
     Pause,
     Menus,
 }
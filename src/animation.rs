@@ -0,0 +1,518 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Context;
+use bevy::{
+    asset::{
+        AssetLoader, AssetPath, LoadContext, LoadedAsset,
+    },
+    math::Rect,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    actions::PlatformerAction,
+    aseprite::AsepriteLoader,
+    components::Player,
+    movement::{
+        horizontal_input, Dash, PlayerState,
+        SimulationClock, State,
+    },
+    GameState,
+};
+
+/// The player's sprite sheet and clips
+pub const PLAYER_ANIMATIONS: &str = "zombie.anim.json";
+
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<SpriteAnimations>()
+            .init_asset_loader::<SpriteAnimationsLoader>()
+            .init_asset_loader::<AsepriteLoader>()
+            .add_system(
+                choose_player_clips
+                    .run_in_state(GameState::Playing),
+            )
+            .add_system(
+                advance_animations
+                    .after(choose_player_clips),
+            )
+            .add_system(
+                sync_frame_colliders
+                    .after(advance_animations),
+            );
+    }
+}
+
+/// What a clip does once its last frame is done
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    #[default]
+    Loop,
+    /// Holds the last frame
+    Once,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    /// Atlas indices, in the order they play
+    pub frames: Vec<usize>,
+    /// How long each of `frames` is shown
+    pub durations: Vec<Duration>,
+    pub mode: PlayMode,
+}
+
+/// Hit and hurt boxes on one frame of a sheet, in
+/// the sheet's pixels from the frame's center, y up
+#[derive(Clone, Debug, Default)]
+pub struct FrameBoxes {
+    /// The size of the frame
+    pub size: Vec2,
+    pub hitboxes: Vec<Rect>,
+    pub hurtboxes: Vec<Rect>,
+}
+
+/// A sprite sheet and the named clips in it, loaded
+/// from an `.anim.json` file or an Aseprite export
+#[derive(Debug, TypeUuid)]
+#[uuid = "481dfe4a-2562-4db0-8963-5a101b46bf10"]
+pub struct SpriteAnimations {
+    pub atlas: Handle<TextureAtlas>,
+    /// The size sprites are drawn at, in world
+    /// pixels. `None` draws them at the sheet's size.
+    pub size: Option<Vec2>,
+    pub clips: HashMap<String, AnimationClip>,
+    /// Indexed by atlas index. Empty for sheets
+    /// without boxes.
+    pub frames: Vec<FrameBoxes>,
+}
+
+/// The `.anim.json` format
+#[derive(Debug, Deserialize)]
+struct AnimationsFile {
+    /// Relative to the `.anim.json` file
+    texture: String,
+    tile_size: [f32; 2],
+    columns: usize,
+    rows: usize,
+    #[serde(default)]
+    size: Option<[f32; 2]>,
+    clips: HashMap<String, ClipFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClipFile {
+    /// The first and last atlas index, inclusive
+    frames: [usize; 2],
+    /// In milliseconds. Either one for every frame,
+    /// or a single one for the whole clip.
+    durations: Vec<u64>,
+    #[serde(default)]
+    mode: PlayMode,
+}
+
+impl ClipFile {
+    fn into_clip(
+        self,
+        frame_count: usize,
+    ) -> anyhow::Result<AnimationClip> {
+        let [first, last] = self.frames;
+        if first > last || last >= frame_count {
+            anyhow::bail!(
+                "frames {first}..={last} aren't in the sheet's {frame_count} frames"
+            );
+        }
+        let frames: Vec<usize> = (first..=last).collect();
+        let durations = match self.durations.as_slice() {
+            [duration] => vec![*duration; frames.len()],
+            durations
+                if durations.len() == frames.len() =>
+            {
+                durations.to_vec()
+            }
+            durations => anyhow::bail!(
+                "{} durations for {} frames",
+                durations.len(),
+                frames.len()
+            ),
+        };
+        if durations.contains(&0) {
+            anyhow::bail!("frames can't last 0ms");
+        }
+        Ok(AnimationClip {
+            frames,
+            durations: durations
+                .into_iter()
+                .map(Duration::from_millis)
+                .collect(),
+            mode: self.mode,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct SpriteAnimationsLoader;
+
+impl AssetLoader for SpriteAnimationsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>>
+    {
+        Box::pin(async move {
+            let file: AnimationsFile =
+                serde_json::from_slice(bytes)?;
+
+            let texture_path = AssetPath::from(
+                load_context
+                    .path()
+                    .parent()
+                    .map_or_else(Default::default, |dir| {
+                        dir.to_path_buf()
+                    })
+                    .join(&file.texture),
+            );
+            let texture = load_context
+                .get_handle(texture_path.clone());
+            let atlas = load_context.set_labeled_asset(
+                "atlas",
+                LoadedAsset::new(TextureAtlas::from_grid(
+                    texture,
+                    Vec2::from(file.tile_size),
+                    file.columns,
+                    file.rows,
+                    None,
+                    None,
+                ))
+                .with_dependency(texture_path),
+            );
+
+            let frame_count = file.columns * file.rows;
+            let mut clips = HashMap::new();
+            for (name, clip) in file.clips {
+                let clip = clip
+                    .into_clip(frame_count)
+                    .with_context(|| {
+                        format!("in clip `{name}`")
+                    })?;
+                clips.insert(name, clip);
+            }
+
+            load_context.set_default_asset(
+                LoadedAsset::new(SpriteAnimations {
+                    atlas,
+                    size: file.size.map(Vec2::from),
+                    clips,
+                    frames: vec![],
+                }),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.json"]
+    }
+}
+
+/// Plays clips from a [`SpriteAnimations`] on the
+/// entity's `TextureAtlasSprite`
+#[derive(Clone, Debug, Component)]
+pub struct Animator {
+    pub animations: Handle<SpriteAnimations>,
+    clip: String,
+    frame: usize,
+    elapsed: Duration,
+    finished: bool,
+}
+
+impl Animator {
+    pub fn new(
+        animations: Handle<SpriteAnimations>,
+        clip: &str,
+    ) -> Self {
+        Self {
+            animations,
+            clip: clip.to_string(),
+            frame: 0,
+            elapsed: Duration::ZERO,
+            finished: false,
+        }
+    }
+
+    pub fn clip(&self) -> &str {
+        &self.clip
+    }
+
+    /// Starts `clip` from its first frame, unless
+    /// it's already playing
+    pub fn play(&mut self, clip: &str) {
+        if self.clip != clip {
+            *self =
+                Self::new(self.animations.clone(), clip);
+        }
+    }
+
+    /// Whether a [`PlayMode::Once`] clip has reached
+    /// its last frame
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+fn advance_animations(
+    time: Res<Time>,
+    animations: Res<Assets<SpriteAnimations>>,
+    mut sprites: Query<(
+        &mut Animator,
+        &mut TextureAtlasSprite,
+        &mut Handle<TextureAtlas>,
+    )>,
+) {
+    for (mut animator, mut sprite, mut atlas) in
+        sprites.iter_mut()
+    {
+        let animator = &mut *animator;
+        let animations =
+            match animations.get(&animator.animations) {
+                Some(animations) => animations,
+                None => continue,
+            };
+        if *atlas != animations.atlas {
+            *atlas = animations.atlas.clone();
+        }
+        if sprite.custom_size != animations.size {
+            sprite.custom_size = animations.size;
+        }
+        let clip =
+            match animations.clips.get(&animator.clip) {
+                Some(clip) => clip,
+                None => continue,
+            };
+
+        // the clip can shrink if the file is reloaded
+        animator.frame =
+            animator.frame.min(clip.frames.len() - 1);
+        if !animator.finished {
+            animator.elapsed += time.delta();
+        }
+        while animator.elapsed
+            >= clip.durations[animator.frame]
+        {
+            animator.elapsed -=
+                clip.durations[animator.frame];
+            if animator.frame + 1 < clip.frames.len() {
+                animator.frame += 1;
+            } else if clip.mode == PlayMode::Loop {
+                animator.frame = 0;
+            } else {
+                animator.finished = true;
+                animator.elapsed = Duration::ZERO;
+            }
+        }
+        sprite.index = clip.frames[animator.frame];
+    }
+}
+
+/// The clip for what the player is doing, named
+/// after the state it's played in
+pub fn player_clip(
+    state: &State,
+    dashing: bool,
+    moving: bool,
+) -> &'static str {
+    // dashing doesn't have a state of its own yet
+    if dashing {
+        return "dash";
+    }
+    match state {
+        State::Idle {} if moving => "run",
+        State::Idle {} => "idle",
+        State::Jumping {} => "jump",
+        State::Falling {} => "fall",
+        State::Crouching {} => "crouch",
+        State::Healing {} => "heal",
+    }
+}
+
+/// Picks the clip for each player's sprite, a
+/// child of the player, from their state machine,
+/// and flips it to face where they're heading
+fn choose_player_clips(
+    clock: Res<SimulationClock>,
+    players: Query<
+        (
+            &PlayerState,
+            &ActionState<PlatformerAction>,
+            &Dash,
+            Option<&KinematicCharacterControllerOutput>,
+        ),
+        With<Player>,
+    >,
+    mut sprites: Query<(
+        &Parent,
+        &mut Animator,
+        &mut TextureAtlasSprite,
+    )>,
+) {
+    for (parent, mut animator, mut sprite) in
+        sprites.iter_mut()
+    {
+        let (state, action_state, dash, output) =
+            match players.get(parent.get()) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+        // walking into a wall isn't running
+        let moving = output.is_some_and(|output| {
+            output.effective_translation.x.abs() > 0.1
+        });
+        animator.play(player_clip(
+            state.state(),
+            dash.dashing(clock.elapsed()),
+            moving,
+        ));
+
+        let input = horizontal_input(action_state);
+        if input != 0. {
+            // the sheet faces right
+            sprite.flip_x = input < 0.;
+        }
+    }
+}
+
+/// A sensor on the current animation frame that
+/// deals damage
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+pub struct Hitbox;
+
+/// A sensor on the current animation frame that
+/// takes damage
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+pub struct Hurtbox;
+
+/// Puts an animated entity's hit and hurt boxes on
+/// another entity, for sprites drawn by a child with
+/// a cosmetic transform of its own
+#[derive(Clone, Copy, Debug, Component)]
+pub struct FrameCollidersOn(pub Entity);
+
+/// The [`Hitbox`] and [`Hurtbox`] sensors spawned
+/// for the frame an animated entity is showing
+#[derive(Clone, Debug, Default, Component)]
+pub struct FrameColliders {
+    index: usize,
+    flip_x: bool,
+    size: Option<Vec2>,
+    boxes: Vec<Entity>,
+}
+
+/// Swaps the hit and hurt box sensors whenever the
+/// shown frame changes
+fn sync_frame_colliders(
+    mut commands: Commands,
+    animations: Res<Assets<SpriteAnimations>>,
+    sprites: Query<
+        (
+            Entity,
+            &Animator,
+            &TextureAtlasSprite,
+            Option<&FrameCollidersOn>,
+            Option<&FrameColliders>,
+        ),
+        Changed<TextureAtlasSprite>,
+    >,
+) {
+    for (entity, animator, sprite, on, current) in
+        sprites.iter()
+    {
+        let animations =
+            match animations.get(&animator.animations) {
+                Some(animations) => animations,
+                None => continue,
+            };
+        if let Some(current) = current {
+            if current.index == sprite.index
+                && current.flip_x == sprite.flip_x
+                && current.size == animations.size
+            {
+                continue;
+            }
+            for collider in current.boxes.iter() {
+                commands
+                    .entity(*collider)
+                    .despawn_recursive();
+            }
+        }
+
+        let mut boxes = vec![];
+        if let Some(frame) =
+            animations.frames.get(sprite.index)
+        {
+            let scale =
+                animations.size.map_or(Vec2::ONE, |size| {
+                    size / frame.size
+                });
+            let shapes = frame
+                .hitboxes
+                .iter()
+                .map(|rect| (*rect, true))
+                .chain(
+                    frame
+                        .hurtboxes
+                        .iter()
+                        .map(|rect| (*rect, false)),
+                );
+            for (rect, hit) in shapes {
+                let mut center = rect.center() * scale;
+                if sprite.flip_x {
+                    center.x = -center.x;
+                }
+                let half_size = rect.half_size() * scale;
+                let mut collider = commands.spawn((
+                    Sensor,
+                    Collider::cuboid(
+                        half_size.x,
+                        half_size.y,
+                    ),
+                    TransformBundle::from(
+                        Transform::from_translation(
+                            center.extend(0.),
+                        ),
+                    ),
+                ));
+                if hit {
+                    collider.insert(Hitbox);
+                } else {
+                    collider.insert(Hurtbox);
+                }
+                let collider = collider.id();
+                commands
+                    .entity(on.map_or(entity, |on| on.0))
+                    .add_child(collider);
+                boxes.push(collider);
+            }
+        }
+        commands.entity(entity).insert(FrameColliders {
+            index: sprite.index,
+            flip_x: sprite.flip_x,
+            size: animations.size,
+            boxes,
+        });
+    }
+}
@@ -0,0 +1,311 @@
+//! Loads sheets exported from Aseprite as
+//! [`SpriteAnimations`].
+//!
+//! Export with File > Export Sprite Sheet, with
+//! "Array" JSON data, tags and slices enabled and
+//! trimming off, and name the data file
+//! `<name>.aseprite.json`. Each tag becomes a clip of
+//! the same name. Slices named `hitbox...` or
+//! `hurtbox...` become the frames' hit and hurt
+//! boxes.
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Context;
+use bevy::{
+    asset::{
+        AssetLoader, AssetPath, LoadContext, LoadedAsset,
+    },
+    math::Rect,
+    prelude::*,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::animation::{
+    AnimationClip, FrameBoxes, PlayMode, SpriteAnimations,
+};
+
+#[derive(Debug, Deserialize)]
+struct AsepriteFile {
+    frames: Vec<Frame>,
+    meta: Meta,
+}
+
+#[derive(Debug, Deserialize)]
+struct Frame {
+    /// Where the frame is in the sheet
+    frame: Bounds,
+    /// In milliseconds
+    duration: u64,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct Bounds {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct Size {
+    w: f32,
+    h: f32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Meta {
+    /// The sheet, relative to the data file
+    image: String,
+    size: Size,
+    #[serde(default)]
+    frame_tags: Vec<Tag>,
+    #[serde(default)]
+    slices: Vec<Slice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: Direction,
+    /// How many times the tag plays, if set.
+    /// Aseprite writes it as a string.
+    #[serde(default)]
+    repeat: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Direction {
+    #[default]
+    Forward,
+    Reverse,
+    Pingpong,
+    PingpongReverse,
+}
+
+#[derive(Debug, Deserialize)]
+struct Slice {
+    name: String,
+    keys: Vec<SliceKey>,
+}
+
+/// A slice's bounds from `frame` onwards, until the
+/// next key
+#[derive(Debug, Deserialize)]
+struct SliceKey {
+    frame: usize,
+    bounds: Bounds,
+}
+
+impl Tag {
+    /// The frames the tag plays, in order
+    fn frames(&self) -> Vec<usize> {
+        let forward: Vec<usize> =
+            (self.from..=self.to).collect();
+        let backward: Vec<usize> =
+            forward.iter().rev().copied().collect();
+        let bounce = |there: &[usize], back: &[usize]| {
+            let mut frames = there.to_vec();
+            // don't show either end twice in a row
+            if back.len() > 2 {
+                frames.extend(&back[1..back.len() - 1]);
+            }
+            frames
+        };
+        match self.direction {
+            Direction::Forward => forward,
+            Direction::Reverse => backward,
+            Direction::Pingpong => {
+                bounce(&forward, &backward)
+            }
+            Direction::PingpongReverse => {
+                bounce(&backward, &forward)
+            }
+        }
+    }
+
+    fn mode(&self) -> PlayMode {
+        match self.repeat.as_deref() {
+            Some("1") => PlayMode::Once,
+            _ => PlayMode::Loop,
+        }
+    }
+}
+
+impl AsepriteFile {
+    fn clips(
+        &self,
+    ) -> anyhow::Result<HashMap<String, AnimationClip>>
+    {
+        let duration = |frame: usize| {
+            Duration::from_millis(
+                self.frames[frame].duration.max(1),
+            )
+        };
+        if self.meta.frame_tags.is_empty() {
+            // untagged sheets play everything
+            let frames: Vec<usize> =
+                (0..self.frames.len()).collect();
+            let durations = frames
+                .iter()
+                .map(|i| duration(*i))
+                .collect();
+            return Ok(HashMap::from([(
+                "default".to_string(),
+                AnimationClip {
+                    frames,
+                    durations,
+                    mode: PlayMode::Loop,
+                },
+            )]));
+        }
+
+        let mut clips = HashMap::new();
+        for tag in self.meta.frame_tags.iter() {
+            if tag.from > tag.to
+                || tag.to >= self.frames.len()
+            {
+                anyhow::bail!(
+                    "tag `{}` covers frames {}..={}, but there are {}",
+                    tag.name,
+                    tag.from,
+                    tag.to,
+                    self.frames.len()
+                );
+            }
+            let frames = tag.frames();
+            clips.insert(
+                tag.name.clone(),
+                AnimationClip {
+                    durations: frames
+                        .iter()
+                        .map(|i| duration(*i))
+                        .collect(),
+                    frames,
+                    mode: tag.mode(),
+                },
+            );
+        }
+        Ok(clips)
+    }
+
+    /// Each frame's hit and hurt boxes, from the
+    /// slices keyed on or before it
+    fn boxes(&self) -> Vec<FrameBoxes> {
+        (0..self.frames.len())
+            .map(|index| {
+                let size = Vec2::new(
+                    self.frames[index].frame.w,
+                    self.frames[index].frame.h,
+                );
+                let mut boxes =
+                    FrameBoxes { size, ..default() };
+                for slice in self.meta.slices.iter() {
+                    let name = slice.name.to_lowercase();
+                    let list = if name.starts_with("hitbox")
+                    {
+                        &mut boxes.hitboxes
+                    } else if name.starts_with("hurtbox") {
+                        &mut boxes.hurtboxes
+                    } else {
+                        continue;
+                    };
+                    if let Some(key) = slice
+                        .keys
+                        .iter()
+                        .filter(|key| key.frame <= index)
+                        .max_by_key(|key| key.frame)
+                    {
+                        list.push(centered(
+                            key.bounds, size,
+                        ));
+                    }
+                }
+                boxes
+            })
+            .collect()
+    }
+}
+
+/// Moves `bounds` from Aseprite's coordinates, from
+/// the frame's top left with y down, to the frame's
+/// center with y up
+fn centered(bounds: Bounds, frame_size: Vec2) -> Rect {
+    let center = Vec2::new(
+        bounds.x + bounds.w / 2. - frame_size.x / 2.,
+        frame_size.y / 2. - (bounds.y + bounds.h / 2.),
+    );
+    Rect::from_center_size(
+        center,
+        Vec2::new(bounds.w, bounds.h),
+    )
+}
+
+#[derive(Default)]
+pub struct AsepriteLoader;
+
+impl AssetLoader for AsepriteLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>>
+    {
+        Box::pin(async move {
+            let file: AsepriteFile =
+                serde_json::from_slice(bytes).context(
+                    "not an Aseprite sheet exported with Array data",
+                )?;
+
+            let texture_path = AssetPath::from(
+                load_context
+                    .path()
+                    .parent()
+                    .map_or_else(Default::default, |dir| {
+                        dir.to_path_buf()
+                    })
+                    .join(&file.meta.image),
+            );
+            let mut atlas = TextureAtlas::new_empty(
+                load_context
+                    .get_handle(texture_path.clone()),
+                Vec2::new(
+                    file.meta.size.w,
+                    file.meta.size.h,
+                ),
+            );
+            for frame in file.frames.iter() {
+                let Bounds { x, y, w, h } = frame.frame;
+                atlas.add_texture(Rect {
+                    min: Vec2::new(x, y),
+                    max: Vec2::new(x + w, y + h),
+                });
+            }
+            let atlas = load_context.set_labeled_asset(
+                "atlas",
+                LoadedAsset::new(atlas)
+                    .with_dependency(texture_path),
+            );
+
+            load_context.set_default_asset(
+                LoadedAsset::new(SpriteAnimations {
+                    atlas,
+                    size: None,
+                    clips: file.clips()?,
+                    frames: file.boxes(),
+                }),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["aseprite.json"]
+    }
+}
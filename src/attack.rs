@@ -0,0 +1,285 @@
+use std::{collections::HashSet, time::Duration};
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    actions::PlatformerAction,
+    camera::gameplay_frozen,
+    combat::{DamageKind, DamageSources, DealDamage},
+    components::{Health, Player},
+    movement::{
+        horizontal_input, PlayerState, SimulationClock,
+        State,
+    },
+    GameState,
+};
+
+/// How long a slash's hitbox stays out
+const SLASH_TIME: Duration = Duration::from_millis(150);
+/// The shortest time between two swings
+const SLASH_COOLDOWN: Duration = Duration::from_millis(300);
+const SLASH_DAMAGE: u32 = 1;
+/// How hard a slash pushes what it hits, in px/s
+const SLASH_KNOCKBACK: f32 = 200.;
+/// How far the hitbox's center is from the player's
+const SLASH_REACH: f32 = 30.;
+/// The hitbox's half size, along and across the
+/// direction of the slash
+const SLASH_HALF_SIZE: Vec2 = Vec2::new(16., 22.);
+/// Drawn over the hitbox, one after the other
+const SLASH_TEXTURES: [&str; 4] = [
+    "particles/slash_01.png",
+    "particles/slash_02.png",
+    "particles/slash_03.png",
+    "particles/slash_04.png",
+];
+
+/// Swinging a sword at enemies with
+/// [`PlatformerAction::Attack`]
+pub struct AttackPlugin;
+
+impl Plugin for AttackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(add_attackers)
+            .add_system(
+                swing
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen),
+            )
+            .add_system(
+                slash_hits
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .label(DamageSources),
+            )
+            .add_system(
+                expire_slashes
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SlashDirection {
+    Up,
+    /// Only in the air, and bounces the player off
+    /// whatever it hits
+    Down,
+    Side,
+}
+
+impl SlashDirection {
+    /// Which way the slash cuts, for a player facing
+    /// `facing`
+    fn vector(self, facing: f32) -> Vec2 {
+        match self {
+            SlashDirection::Up => Vec2::Y,
+            SlashDirection::Down => Vec2::NEG_Y,
+            SlashDirection::Side => Vec2::X * facing,
+        }
+    }
+}
+
+/// Which way a player is facing and when they can
+/// swing again
+#[derive(Clone, Copy, Debug, Component)]
+pub struct Attacker {
+    /// 1 for right, -1 for left
+    pub facing: f32,
+    ready_at: Duration,
+    swings: usize,
+}
+
+impl Default for Attacker {
+    fn default() -> Self {
+        Self {
+            facing: 1.,
+            ready_at: Duration::ZERO,
+            swings: 0,
+        }
+    }
+}
+
+/// A swing's hitbox, a child of the player swinging
+#[derive(Clone, Debug, Component)]
+pub struct Slash {
+    pub direction: SlashDirection,
+    pub owner: Entity,
+    /// Which way hits are pushed
+    push: Vec2,
+    until: Duration,
+    /// Everything already hit, which this swing
+    /// can't hit again
+    hit: HashSet<Entity>,
+}
+
+fn add_attackers(
+    mut commands: Commands,
+    players: Query<Entity, Added<Player>>,
+) {
+    for player in players.iter() {
+        commands.entity(player).insert(Attacker::default());
+    }
+}
+
+fn swing(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    clock: Res<SimulationClock>,
+    mut players: Query<(
+        Entity,
+        &ActionState<PlatformerAction>,
+        &PlayerState,
+        &mut Attacker,
+    )>,
+) {
+    let now = clock.elapsed();
+    for (player, action_state, state, mut attacker) in
+        players.iter_mut()
+    {
+        let steering = horizontal_input(action_state);
+        if steering != 0. {
+            attacker.facing = steering.signum();
+        }
+        if !action_state
+            .just_pressed(PlatformerAction::Attack)
+            || now < attacker.ready_at
+        {
+            continue;
+        }
+
+        let airborne = matches!(
+            state.state(),
+            State::Jumping {} | State::Falling {}
+        );
+        let direction = if action_state
+            .pressed(PlatformerAction::Up)
+        {
+            SlashDirection::Up
+        } else if airborne
+            && action_state.pressed(PlatformerAction::Down)
+        {
+            SlashDirection::Down
+        } else {
+            SlashDirection::Side
+        };
+        let push = direction.vector(attacker.facing);
+        let texture = SLASH_TEXTURES
+            [attacker.swings % SLASH_TEXTURES.len()];
+        attacker.swings += 1;
+        attacker.ready_at = now + SLASH_COOLDOWN;
+
+        commands.entity(player).with_children(|parent| {
+            parent.spawn((
+                SpriteBundle {
+                    texture: asset_server.load(texture),
+                    sprite: Sprite {
+                        custom_size: Some(
+                            SLASH_HALF_SIZE * 2.5,
+                        ),
+                        ..default()
+                    },
+                    // the sprite and hitbox both point
+                    // along x, turned to face the slash
+                    transform: Transform::from_translation(
+                        (push * SLASH_REACH).extend(1.),
+                    )
+                    .with_rotation(Quat::from_rotation_z(
+                        push.y.atan2(push.x),
+                    )),
+                    ..default()
+                },
+                Collider::cuboid(
+                    SLASH_HALF_SIZE.x,
+                    SLASH_HALF_SIZE.y,
+                ),
+                Sensor,
+                ActiveCollisionTypes::default()
+                    | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
+                Slash {
+                    direction,
+                    owner: player,
+                    push,
+                    until: now + SLASH_TIME,
+                    hit: HashSet::new(),
+                },
+            ));
+        });
+    }
+}
+
+/// Damages everything each slash touches, once
+fn slash_hits(
+    clock: Res<SimulationClock>,
+    rapier_context: Res<RapierContext>,
+    mut slashes: Query<(Entity, &mut Slash)>,
+    parents: Query<&Parent>,
+    targets: Query<(), (With<Health>, Without<Player>)>,
+    mut players: Query<&mut PlayerState>,
+    mut damage: EventWriter<DealDamage>,
+) {
+    for (entity, mut slash) in slashes.iter_mut() {
+        let touching: Vec<Entity> = rapier_context
+            .intersections_with(entity)
+            .filter(|(_, _, intersecting)| *intersecting)
+            .map(
+                |(a, b, _)| if a == entity { b } else { a },
+            )
+            .collect();
+        for collider in touching {
+            // colliders can be parts of what they
+            // belong to, like an enemy's contact area
+            let target = if targets.contains(collider) {
+                collider
+            } else {
+                match parents.get(collider) {
+                    Ok(parent)
+                        if targets
+                            .contains(parent.get()) =>
+                    {
+                        parent.get()
+                    }
+                    _ => continue,
+                }
+            };
+            let first_hit = slash.hit.is_empty();
+            if !slash.hit.insert(target) {
+                continue;
+            }
+
+            damage.send(DealDamage {
+                target,
+                source: slash.owner,
+                amount: SLASH_DAMAGE,
+                kind: DamageKind::Slash,
+                knockback: slash.push * SLASH_KNOCKBACK,
+            });
+            if slash.direction == SlashDirection::Down
+                && first_hit
+            {
+                // pogo
+                if let Ok(mut state) =
+                    players.get_mut(slash.owner)
+                {
+                    state.bounce(clock.elapsed());
+                }
+            }
+        }
+    }
+}
+
+fn expire_slashes(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    slashes: Query<(Entity, &Slash)>,
+) {
+    for (entity, slash) in slashes.iter() {
+        if clock.elapsed() >= slash.until {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
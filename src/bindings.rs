@@ -0,0 +1,524 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::PlatformerAction,
+    config,
+    menu::{
+        spawn_menu, MenuBack, MenuConfirmed, MenuSystem,
+    },
+    settings::Settings,
+};
+
+/// Where bindings were kept before they moved into
+/// the [`Settings`]
+const BINDINGS_FILE: &str = "bindings.json";
+
+/// Actions that can be rebound from the controls
+/// menu. `Horizontal` is an analog axis and always
+/// uses the left stick.
+const REBINDABLE: [PlatformerAction; 10] = [
+    PlatformerAction::Up,
+    PlatformerAction::Down,
+    PlatformerAction::Left,
+    PlatformerAction::Right,
+    PlatformerAction::Jump,
+    PlatformerAction::Heal,
+    PlatformerAction::Dash,
+    PlatformerAction::Attack,
+    PlatformerAction::Pause,
+    PlatformerAction::Menus,
+];
+
+pub struct BindingsPlugin;
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        // the settings are loaded in `main`, before
+        // the window is made
+        let profiles = app
+            .world
+            .get_resource::<Settings>()
+            .map(|settings| settings.bindings.clone())
+            .unwrap_or_default();
+        app.insert_resource(profiles.input_map())
+            .insert_resource(profiles)
+            .add_system(apply_profiles)
+            .add_system(
+                capture_rebinding
+                    .before(MenuSystem::Navigate),
+            )
+            .add_system(
+                controls_menu_actions
+                    .after(MenuSystem::Navigate),
+            )
+            .add_system(
+                refresh_controls_menu
+                    .after(controls_menu_actions)
+                    .after(capture_rebinding),
+            );
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+pub enum Profile {
+    #[default]
+    Keyboard,
+    Gamepad,
+}
+
+/// Where a player's input comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InputSource {
+    Gamepad(Gamepad),
+    /// The rebindable keyboard profile
+    KeyboardLeft,
+    /// The arrow keys side of a shared keyboard
+    KeyboardRight,
+}
+
+/// The player's bindings, kept apart so that
+/// rebinding a key never touches the gamepad and
+/// the other way around
+#[derive(
+    Clone, Debug, Resource, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct InputProfiles {
+    pub keyboard: InputMap<PlatformerAction>,
+    pub gamepad: InputMap<PlatformerAction>,
+}
+
+impl Default for InputProfiles {
+    fn default() -> Self {
+        Self {
+            keyboard: default_keyboard_map(),
+            gamepad: default_gamepad_map(),
+        }
+    }
+}
+
+impl InputProfiles {
+    /// Reads the profiles from the bindings file
+    /// older versions saved, falling back to the
+    /// defaults
+    pub fn load() -> Self {
+        match config::load::<Self>(BINDINGS_FILE) {
+            Ok(Some(mut profiles)) => {
+                profiles.bind_missing();
+                profiles
+            }
+            Ok(None) => Self::default(),
+            Err(error) => {
+                warn!("couldn't load bindings: {error:?}");
+                Self::default()
+            }
+        }
+    }
+
+    /// Gives actions added since the profiles were
+    /// saved their default bindings, unless another
+    /// action has taken them
+    pub fn bind_missing(&mut self) {
+        let defaults = Self::default();
+        for profile in [Profile::Keyboard, Profile::Gamepad]
+        {
+            let input_map = self.profile_mut(profile);
+            for action in PlatformerAction::variants() {
+                if !input_map.get(action).is_empty() {
+                    continue;
+                }
+                let unused: Vec<UserInput> = defaults
+                    .profile(profile)
+                    .get(action)
+                    .iter()
+                    .filter(|input| {
+                        !is_bound(input_map, input)
+                    })
+                    .cloned()
+                    .collect();
+                for input in unused {
+                    input_map.insert(input, action);
+                }
+            }
+        }
+    }
+
+    pub fn profile(
+        &self,
+        profile: Profile,
+    ) -> &InputMap<PlatformerAction> {
+        match profile {
+            Profile::Keyboard => &self.keyboard,
+            Profile::Gamepad => &self.gamepad,
+        }
+    }
+
+    pub fn profile_mut(
+        &mut self,
+        profile: Profile,
+    ) -> &mut InputMap<PlatformerAction> {
+        match profile {
+            Profile::Keyboard => &mut self.keyboard,
+            Profile::Gamepad => &mut self.gamepad,
+        }
+    }
+
+    /// Both profiles merged into the map used by
+    /// players and menus
+    pub fn input_map(&self) -> InputMap<PlatformerAction> {
+        let mut input_map = self.keyboard.clone();
+        input_map.merge(&self.gamepad);
+        input_map
+    }
+
+    /// The bindings for a single player using
+    /// `source`
+    pub fn input_map_for(
+        &self,
+        source: InputSource,
+    ) -> InputMap<PlatformerAction> {
+        match source {
+            InputSource::Gamepad(gamepad) => {
+                let mut input_map = self.gamepad.clone();
+                input_map.set_gamepad(gamepad);
+                input_map
+            }
+            InputSource::KeyboardLeft => {
+                self.keyboard.clone()
+            }
+            InputSource::KeyboardRight => {
+                right_keyboard_map()
+            }
+        }
+    }
+
+    pub fn reset(&mut self, profile: Profile) {
+        *self.profile_mut(profile) = match profile {
+            Profile::Keyboard => default_keyboard_map(),
+            Profile::Gamepad => default_gamepad_map(),
+        };
+    }
+}
+
+pub fn default_keyboard_map() -> InputMap<PlatformerAction>
+{
+    use PlatformerAction::*;
+
+    InputMap::new([
+        (KeyCode::W, Up),
+        (KeyCode::S, Down),
+        (KeyCode::A, Left),
+        (KeyCode::D, Right),
+        (KeyCode::Space, Jump),
+        (KeyCode::E, Dash),
+        (KeyCode::J, Attack),
+        (KeyCode::Q, Heal),
+        (KeyCode::Return, Pause),
+        (KeyCode::I, Menus),
+    ])
+}
+
+/// Bindings for a second player sharing the
+/// keyboard. These stay clear of the default
+/// keyboard profile and can't be rebound.
+pub fn right_keyboard_map() -> InputMap<PlatformerAction> {
+    use PlatformerAction::*;
+
+    InputMap::new([
+        (KeyCode::Up, Up),
+        (KeyCode::Down, Down),
+        (KeyCode::Left, Left),
+        (KeyCode::Right, Right),
+        (KeyCode::RControl, Jump),
+        (KeyCode::RShift, Dash),
+        (KeyCode::Slash, Attack),
+        (KeyCode::RAlt, Heal),
+        (KeyCode::Back, Pause),
+        (KeyCode::Delete, Menus),
+    ])
+}
+
+pub fn default_gamepad_map() -> InputMap<PlatformerAction> {
+    use PlatformerAction::*;
+
+    let mut input_map = InputMap::new([
+        (GamepadButtonType::DPadUp, Up),
+        (GamepadButtonType::DPadDown, Down),
+        (GamepadButtonType::DPadLeft, Left),
+        (GamepadButtonType::DPadRight, Right),
+        (GamepadButtonType::South, Jump),
+        (GamepadButtonType::RightTrigger2, Dash),
+        (GamepadButtonType::West, Attack),
+        (GamepadButtonType::East, Heal),
+        (GamepadButtonType::Start, Pause),
+        (GamepadButtonType::Select, Menus),
+    ]);
+    input_map.insert(
+        SingleAxis::symmetric(
+            GamepadAxisType::LeftStickX,
+            0.1,
+        ),
+        Horizontal,
+    );
+    input_map
+}
+
+/// Whether any action is bound to `input`
+fn is_bound(
+    input_map: &InputMap<PlatformerAction>,
+    input: &UserInput,
+) -> bool {
+    PlatformerAction::variants().any(|action| {
+        input_map
+            .get(action)
+            .iter()
+            .any(|bound| bound == input)
+    })
+}
+
+/// Binds `input` to `action`, replacing the
+/// action's other bindings.
+///
+/// If another action was already using `input` it
+/// takes over `action`'s old bindings, so nothing is
+/// left unbound.
+pub fn rebind(
+    input_map: &mut InputMap<PlatformerAction>,
+    action: PlatformerAction,
+    input: UserInput,
+) {
+    let previous: Vec<UserInput> =
+        input_map.get(action).iter().cloned().collect();
+    for other in PlatformerAction::variants() {
+        if other != action
+            && input_map
+                .remove(other, input.clone())
+                .is_some()
+        {
+            for old in previous.iter() {
+                input_map.insert(old.clone(), other);
+            }
+        }
+    }
+    input_map.clear_action(action);
+    input_map.insert(input, action);
+}
+
+/// Keeps the menus' [`InputMap`] in sync with the
+/// [`InputProfiles`]. Players get theirs from
+/// `gamepad::apply_slot_bindings`.
+fn apply_profiles(
+    profiles: Res<InputProfiles>,
+    mut menu_map: ResMut<InputMap<PlatformerAction>>,
+) {
+    if profiles.is_changed() {
+        *menu_map = profiles.input_map();
+    }
+}
+
+/// The controls menu's root entity, along with the
+/// profile being edited
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct ControlsMenu {
+    pub profile: Profile,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+enum ControlsItem {
+    Profile,
+    Action(PlatformerAction),
+    Reset,
+    Back,
+}
+
+/// Waiting for the next key or button press to
+/// bind to `action`
+#[derive(Clone, Copy, Debug, Resource)]
+pub struct Rebinding {
+    pub action: PlatformerAction,
+    pub profile: Profile,
+}
+
+/// Opens the controls menu on top of whatever else
+/// is open
+pub fn spawn_controls_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    depth: i32,
+) -> Entity {
+    spawn_menu(
+        commands,
+        asset_server,
+        "Controls",
+        depth,
+        ControlsMenu::default(),
+        |menu| {
+            menu.item("", ControlsItem::Profile);
+            for action in REBINDABLE {
+                menu.item("", ControlsItem::Action(action));
+            }
+            menu.item(
+                "Reset to defaults",
+                ControlsItem::Reset,
+            );
+            menu.item("Back", ControlsItem::Back);
+            menu.label("Esc cancels rebinding", ());
+        },
+    )
+}
+
+fn controls_menu_actions(
+    mut commands: Commands,
+    mut confirmed: EventReader<MenuConfirmed>,
+    mut back: EventReader<MenuBack>,
+    items: Query<(&ControlsItem, &Parent)>,
+    mut menus: Query<&mut ControlsMenu>,
+    mut profiles: ResMut<InputProfiles>,
+) {
+    for MenuConfirmed(item) in confirmed.iter() {
+        let (item, parent) = match items.get(*item) {
+            Ok(item) => item,
+            Err(_) => continue,
+        };
+        let mut menu = match menus.get_mut(parent.get()) {
+            Ok(menu) => menu,
+            Err(_) => continue,
+        };
+        match item {
+            ControlsItem::Profile => {
+                menu.profile = match menu.profile {
+                    Profile::Keyboard => Profile::Gamepad,
+                    Profile::Gamepad => Profile::Keyboard,
+                };
+            }
+            ControlsItem::Action(action) => {
+                commands.insert_resource(Rebinding {
+                    action: *action,
+                    profile: menu.profile,
+                });
+            }
+            ControlsItem::Reset => {
+                profiles.reset(menu.profile);
+            }
+            ControlsItem::Back => {
+                commands
+                    .entity(parent.get())
+                    .despawn_recursive();
+            }
+        }
+    }
+    for MenuBack(menu) in back.iter() {
+        if menus.contains(*menu) {
+            commands.entity(*menu).despawn_recursive();
+        }
+    }
+}
+
+/// Binds the next key or button pressed. Runs before
+/// menu navigation so the press doesn't also select
+/// something.
+fn capture_rebinding(
+    mut commands: Commands,
+    rebinding: Option<Res<Rebinding>>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut profiles: ResMut<InputProfiles>,
+    mut menu_actions: ResMut<ActionState<PlatformerAction>>,
+) {
+    let rebinding = match rebinding {
+        Some(rebinding) => rebinding,
+        None => return,
+    };
+    // swallow menu input until the rebinding is over,
+    // and until the keys pressed for it are released
+    for action in PlatformerAction::variants() {
+        menu_actions.consume(action);
+    }
+
+    if keys.just_pressed(KeyCode::Escape) {
+        commands.remove_resource::<Rebinding>();
+        return;
+    }
+
+    let input: Option<UserInput> = match rebinding.profile {
+        Profile::Keyboard => keys
+            .get_just_pressed()
+            .next()
+            .map(|key| (*key).into()),
+        Profile::Gamepad => buttons
+            .get_just_pressed()
+            .next()
+            .map(|button| button.button_type.into()),
+    };
+    if let Some(input) = input {
+        rebind(
+            profiles.profile_mut(rebinding.profile),
+            rebinding.action,
+            input,
+        );
+        commands.remove_resource::<Rebinding>();
+    }
+}
+
+fn refresh_controls_menu(
+    profiles: Res<InputProfiles>,
+    rebinding: Option<Res<Rebinding>>,
+    menus: Query<&ControlsMenu>,
+    mut items: Query<(&ControlsItem, &Parent, &mut Text)>,
+) {
+    for (item, parent, mut text) in items.iter_mut() {
+        let menu = match menus.get(parent.get()) {
+            Ok(menu) => menu,
+            Err(_) => continue,
+        };
+        let value = match item {
+            ControlsItem::Profile => {
+                format!("Profile: {:?}", menu.profile)
+            }
+            ControlsItem::Action(action) => {
+                let waiting =
+                    rebinding.as_ref().is_some_and(|r| {
+                        r.action == *action
+                            && r.profile == menu.profile
+                    });
+                if waiting {
+                    format!("{action:?}: press a key...")
+                } else {
+                    format!(
+                        "{action:?}: {}",
+                        describe(
+                            profiles.profile(menu.profile),
+                            *action,
+                        )
+                    )
+                }
+            }
+            _ => continue,
+        };
+        // only touch the text when it changes, to keep
+        // the ui from relayouting every frame
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+fn describe(
+    input_map: &InputMap<PlatformerAction>,
+    action: PlatformerAction,
+) -> String {
+    let inputs: Vec<String> = input_map
+        .get(action)
+        .iter()
+        .map(|input| input.to_string())
+        .collect();
+    if inputs.is_empty() {
+        "-".to_string()
+    } else {
+        inputs.join(", ")
+    }
+}
@@ -0,0 +1,757 @@
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    math::Rect,
+    prelude::*,
+    render::{
+        camera::{
+            OrthographicProjection, RenderTarget,
+            ScalingMode, Viewport,
+        },
+        view::RenderLayers,
+    },
+    window::WindowResized,
+};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use std::time::Duration;
+
+use crate::{
+    combat::HitStop,
+    components::{CameraZone, Player},
+    pixel_perfect::{
+        snap_to_pixels, PixelPerfectTarget, SubpixelOffset,
+    },
+};
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelTransitionSettings>()
+            .init_resource::<CameraFitSettings>()
+            .init_resource::<CameraLevel>()
+            .init_resource::<CameraBounds>()
+            .add_startup_system(spawn_letterbox_camera)
+            .add_startup_system(spawn_ui_camera)
+            .add_system(fit_camera_viewport)
+            .add_system(blend_camera_zones)
+            .add_system(
+                camera_fit_inside_current_level
+                    .after(fit_camera_viewport)
+                    .after(blend_camera_zones),
+            )
+            .add_system(
+                freeze_physics_during_transition
+                    .after(camera_fit_inside_current_level),
+            );
+    }
+}
+
+/// The camera that follows the player around the
+/// level
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+pub struct MainCamera;
+
+/// Clears the bars around the [`MainCamera`]'s
+/// viewport
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct LetterboxCamera;
+
+/// Draws menus and the hud over the whole window,
+/// on top of every other camera. The other cameras
+/// have their ui turned off, otherwise it would be
+/// drawn once per camera, squeezed into each one's
+/// viewport.
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+pub struct UiCamera;
+
+/// How the camera's view is fit into a window
+/// whose aspect ratio doesn't match
+/// [`CameraFitSettings::aspect_ratio`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FitMode {
+    /// Bars above and below when the window is too
+    /// tall, bars on the sides when it is too wide
+    Fit,
+    /// Bars above and below when the window is too
+    /// tall, otherwise show more of the level
+    /// horizontally
+    Letterbox,
+    /// Bars on the sides when the window is too
+    /// wide, otherwise show more of the level
+    /// vertically
+    Pillarbox,
+    /// Never draw bars, always use the window's
+    /// aspect ratio
+    Expand,
+}
+
+#[derive(Clone, Debug, Resource)]
+pub struct CameraFitSettings {
+    /// width / height
+    pub aspect_ratio: f32,
+    pub mode: FitMode,
+}
+
+impl Default for CameraFitSettings {
+    fn default() -> Self {
+        Self {
+            aspect_ratio: 16. / 9.,
+            mode: FitMode::Fit,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LevelTransitionMode {
+    /// The camera jumps straight to the new level
+    Instant,
+    /// The camera slides from the old level's view
+    /// to the new level's view over
+    /// [`LevelTransitionSettings::duration`]
+    Slide,
+}
+
+/// How the camera moves when the player crosses
+/// into a neighbouring level
+#[derive(Clone, Debug, Resource)]
+pub struct LevelTransitionSettings {
+    pub mode: LevelTransitionMode,
+    pub duration: Duration,
+    /// Stop player input and physics while the
+    /// camera is sliding, like Celeste's room
+    /// transitions
+    pub freeze_gameplay: bool,
+}
+
+impl Default for LevelTransitionSettings {
+    fn default() -> Self {
+        Self {
+            mode: LevelTransitionMode::Slide,
+            duration: Duration::from_millis(400),
+            freeze_gameplay: true,
+        }
+    }
+}
+
+/// The iid of the level the camera is currently
+/// fitted to
+#[derive(Debug, Default, Resource)]
+pub struct CameraLevel(Option<String>);
+
+impl CameraLevel {
+    pub fn iid(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+/// The world-space bounds of the level the camera
+/// is fitted to, for anything that moves the camera
+/// afterwards and must keep it inside the level
+#[derive(Debug, Default, Resource)]
+pub struct CameraBounds(pub Option<Rect>);
+
+/// An in-progress slide between two levels.
+///
+/// Only exists while the camera is moving.
+#[derive(Debug, Resource)]
+pub struct LevelTransition {
+    from: Rect,
+    timer: Timer,
+}
+
+/// Run condition for systems that should stop
+/// while the camera slides between levels, or
+/// during a hit-stop
+pub fn gameplay_frozen(
+    transition: Option<Res<LevelTransition>>,
+    settings: Option<Res<LevelTransitionSettings>>,
+    hit_stop: Option<Res<HitStop>>,
+) -> bool {
+    let transitioning = transition.is_some()
+        && settings.is_some_and(|settings| {
+            settings.freeze_gameplay
+        });
+    transitioning
+        || hit_stop
+            .is_some_and(|hit_stop| hit_stop.frames > 0)
+}
+
+/// Finds the part of a render target, in physical
+/// pixels, that the camera should draw to.
+///
+/// Returns the viewport's position and size.
+pub fn fit_viewport(
+    target: UVec2,
+    aspect_ratio: f32,
+    mode: FitMode,
+) -> (UVec2, UVec2) {
+    let target_size = target.as_vec2();
+    let target_ratio = target_size.x / target_size.y;
+
+    let too_wide = target_ratio > aspect_ratio;
+    let bars = match mode {
+        FitMode::Fit => true,
+        FitMode::Letterbox => !too_wide,
+        FitMode::Pillarbox => too_wide,
+        FitMode::Expand => false,
+    };
+    if !bars {
+        return (UVec2::ZERO, target);
+    }
+
+    let size = if too_wide {
+        Vec2::new(
+            target_size.y * aspect_ratio,
+            target_size.y,
+        )
+    } else {
+        Vec2::new(
+            target_size.x,
+            target_size.x / aspect_ratio,
+        )
+    }
+    .round()
+    .as_uvec2()
+    .max(UVec2::ONE)
+    .min(target);
+
+    ((target - size) / 2, size)
+}
+
+/// Finds the area of the world a camera with the
+/// given aspect ratio should show for a level,
+/// following the player along the level's longer
+/// axis.
+///
+/// Both the level and the returned rect are in
+/// world coordinates.
+pub fn fit_inside_level(
+    level: Rect,
+    player: Vec2,
+    aspect_ratio: f32,
+) -> Rect {
+    let level_ratio = level.width() / level.height();
+
+    let (size, offset) = if level_ratio > aspect_ratio {
+        // level is wider than the screen
+        let height = level.height();
+        let width = height * aspect_ratio;
+        let x = (player.x - level.min.x - width / 2.)
+            .clamp(0., level.width() - width);
+        (Vec2::new(width, height), Vec2::new(x, 0.))
+    } else {
+        // level is taller than the screen
+        let width = level.width();
+        let height = width / aspect_ratio;
+        let y = (player.y - level.min.y - height / 2.)
+            .clamp(0., level.height() - height);
+        (Vec2::new(width, height), Vec2::new(0., y))
+    };
+
+    let min = level.min + offset;
+    Rect {
+        min,
+        max: min + size,
+    }
+}
+
+/// Finds the area of the world a camera of a fixed
+/// size should show, centered on the player but
+/// kept inside the level.
+///
+/// Levels smaller than the camera are centered
+/// along that axis instead.
+pub fn follow_inside_level(
+    level: Rect,
+    player: Vec2,
+    size: Vec2,
+) -> Rect {
+    let follow_axis =
+        |player: f32, min: f32, max: f32, size: f32| {
+            if max - min < size {
+                (min + max - size) / 2.
+            } else {
+                (player - size / 2.).clamp(min, max - size)
+            }
+        };
+
+    let min = Vec2::new(
+        follow_axis(
+            player.x,
+            level.min.x,
+            level.max.x,
+            size.x,
+        ),
+        follow_axis(
+            player.y,
+            level.min.y,
+            level.max.y,
+            size.y,
+        ),
+    );
+    Rect {
+        min,
+        max: min + size,
+    }
+}
+
+/// Applies every active [`CameraZone`] to a view,
+/// lowest priority first so that higher priority
+/// zones win where they overlap.
+///
+/// Zooming is skipped when `zoom` is `false`: the
+/// pixel-perfect target always shows the same
+/// number of world pixels.
+fn apply_camera_zones<'a>(
+    view: Rect,
+    player: Vec2,
+    level: Rect,
+    zoom: bool,
+    zones: impl Iterator<Item = (&'a CameraZone, Vec2)>,
+) -> Rect {
+    let mut zones = zones
+        .filter(|(zone, _)| zone.weight > 0.)
+        .collect::<Vec<_>>();
+    zones.sort_by_key(|(zone, _)| zone.priority);
+
+    zones.into_iter().fold(view, |view, (zone, center)| {
+        let zone_rect = zone.rect(center);
+        let mut focus = player;
+        if zone.lock_x {
+            focus.x = center.x;
+        }
+        if zone.lock_y {
+            focus.y = center.y;
+        }
+        let bounds = if zone.bounded {
+            zone_rect.intersect(level)
+        } else {
+            level
+        };
+        let size = if zoom {
+            view.size() * zone.zoom
+        } else {
+            view.size()
+        };
+        let zoned =
+            follow_inside_level(bounds, focus, size);
+        lerp_rect(view, zoned, ease_in_out(zone.weight))
+    })
+}
+
+/// Fades [`CameraZone`]s in while the player is
+/// inside of them and out once they leave
+fn blend_camera_zones(
+    time: Res<Time>,
+    player_query: Query<&Transform, With<Player>>,
+    mut zones: Query<(&mut CameraZone, &GlobalTransform)>,
+) {
+    let player = players_center(player_query.iter())
+        .map(|center| center.truncate());
+
+    for (mut zone, transform) in zones.iter_mut() {
+        let inside = player.is_some_and(|player| {
+            zone.rect(transform.translation().truncate())
+                .contains(player)
+        });
+        let step = if zone.blend > 0. {
+            time.delta_seconds() / zone.blend
+        } else {
+            1.
+        };
+        let weight = if inside {
+            (zone.weight + step).min(1.)
+        } else {
+            (zone.weight - step).max(0.)
+        };
+        if weight != zone.weight {
+            zone.weight = weight;
+        }
+    }
+}
+
+/// The point the camera follows: the middle of all
+/// the players, so local multiplayer keeps everyone
+/// in view as much as the level allows
+pub fn players_center<'a>(
+    players: impl Iterator<Item = &'a Transform>,
+) -> Option<Vec3> {
+    let (sum, count) = players.fold(
+        (Vec3::ZERO, 0),
+        |(sum, count), transform| {
+            (sum + transform.translation, count + 1)
+        },
+    );
+    if count == 0 {
+        None
+    } else {
+        Some(sum / count as f32)
+    }
+}
+
+fn ease_in_out(t: f32) -> f32 {
+    t * t * (3. - 2. * t)
+}
+
+fn lerp_rect(from: Rect, to: Rect, t: f32) -> Rect {
+    Rect {
+        min: from.min.lerp(to.min, t),
+        max: from.max.lerp(to.max, t),
+    }
+}
+
+pub fn camera_fit_inside_current_level(
+    mut commands: Commands,
+    mut camera_query: Query<
+        (
+            &Camera,
+            &mut OrthographicProjection,
+            &mut Transform,
+        ),
+        (With<MainCamera>, Without<Player>),
+    >,
+    player_query: Query<&Transform, With<Player>>,
+    level_query: Query<
+        (&Transform, &Handle<LdtkLevel>),
+        (Without<OrthographicProjection>, Without<Player>),
+    >,
+    level_selection: Res<LevelSelection>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    settings: Res<LevelTransitionSettings>,
+    fit_settings: Res<CameraFitSettings>,
+    mut camera_level: ResMut<CameraLevel>,
+    mut camera_bounds: ResMut<CameraBounds>,
+    mut transition: Option<ResMut<LevelTransition>>,
+    pixel_target: Option<Res<PixelPerfectTarget>>,
+    mut subpixel: Option<ResMut<SubpixelOffset>>,
+    zones: Query<(&CameraZone, &GlobalTransform)>,
+    time: Res<Time>,
+) {
+    if let Some(player_translation) =
+        players_center(player_query.iter())
+    {
+        let (
+            camera,
+            mut orthographic_projection,
+            mut camera_transform,
+        ) = match camera_query.get_single_mut() {
+            Ok(camera) => camera,
+            Err(_) => return,
+        };
+        let aspect_ratio =
+            match camera.physical_viewport_size() {
+                Some(size) if size.x > 0 && size.y > 0 => {
+                    size.x as f32 / size.y as f32
+                }
+                _ => fit_settings.aspect_ratio,
+            };
+
+        for (level_transform, level_handle) in
+            level_query.iter()
+        {
+            if let Some(ldtk_level) =
+                ldtk_levels.get(level_handle)
+            {
+                let level = &ldtk_level.level;
+                if !level_selection.is_match(&0, level) {
+                    continue;
+                }
+
+                let level_min =
+                    level_transform.translation.truncate();
+                let level_rect = Rect {
+                    min: level_min,
+                    max: level_min
+                        + Vec2::new(
+                            level.px_wid as f32,
+                            level.px_hei as f32,
+                        ),
+                };
+                camera_bounds.0 = Some(level_rect);
+
+                let target = match &pixel_target {
+                    Some(pixel_target) => {
+                        follow_inside_level(
+                            level_rect,
+                            player_translation.truncate(),
+                            pixel_target.size.as_vec2(),
+                        )
+                    }
+                    None => fit_inside_level(
+                        level_rect,
+                        player_translation.truncate(),
+                        aspect_ratio,
+                    ),
+                };
+                let target = apply_camera_zones(
+                    target,
+                    player_translation.truncate(),
+                    level_rect,
+                    pixel_target.is_none(),
+                    zones.iter().map(
+                        |(zone, transform)| {
+                            (
+                                zone,
+                                transform
+                                    .translation()
+                                    .truncate(),
+                            )
+                        },
+                    ),
+                );
+
+                if camera_level.0.as_ref()
+                    != Some(&level.iid)
+                {
+                    let previous = camera_level
+                        .0
+                        .replace(level.iid.clone());
+                    // only slide between two levels, not
+                    // when the first level is shown
+                    if previous.is_some()
+                        && settings.mode
+                            == LevelTransitionMode::Slide
+                    {
+                        commands.insert_resource(
+                            LevelTransition {
+                                from: current_rect(
+                                    &orthographic_projection,
+                                    &camera_transform,
+                                ),
+                                timer: Timer::new(
+                                    settings.duration,
+                                    TimerMode::Once,
+                                ),
+                            },
+                        );
+                        // the transition starts next frame,
+                        // hold the old view until then
+                        return;
+                    }
+                }
+
+                let rect = match transition.as_mut() {
+                    Some(transition) => {
+                        transition.timer.tick(time.delta());
+                        if transition.timer.finished() {
+                            commands.remove_resource::<
+                                LevelTransition,
+                            >();
+                        }
+                        lerp_rect(
+                            transition.from,
+                            target,
+                            ease_in_out(
+                                transition.timer.percent(),
+                            ),
+                        )
+                    }
+                    None => target,
+                };
+
+                let rect = match &pixel_target {
+                    Some(pixel_target) => {
+                        let (snapped, remainder) =
+                            snap_to_pixels(
+                                rect,
+                                pixel_target.size,
+                                pixel_target.margin,
+                            );
+                        if let Some(subpixel) =
+                            subpixel.as_mut()
+                        {
+                            // without a margin there is
+                            // nothing to shift into
+                            subpixel.0 = if pixel_target
+                                .margin
+                                > 0
+                            {
+                                remainder
+                            } else {
+                                Vec2::ZERO
+                            };
+                        }
+                        snapped
+                    }
+                    None => rect,
+                };
+
+                apply_rect(
+                    rect,
+                    &mut orthographic_projection,
+                    &mut camera_transform,
+                );
+            }
+        }
+    }
+}
+
+/// The area of the world the camera currently shows
+fn current_rect(
+    orthographic_projection: &OrthographicProjection,
+    camera_transform: &Transform,
+) -> Rect {
+    let min = camera_transform.translation.truncate()
+        + Vec2::new(
+            orthographic_projection.left,
+            orthographic_projection.bottom,
+        );
+    Rect {
+        min,
+        max: min
+            + Vec2::new(
+                orthographic_projection.right
+                    - orthographic_projection.left,
+                orthographic_projection.top
+                    - orthographic_projection.bottom,
+            ),
+    }
+}
+
+fn spawn_letterbox_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                priority: -1,
+                ..default()
+            },
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::Custom(
+                    Color::BLACK,
+                ),
+            },
+            ..default()
+        },
+        // a layer nothing else is on, so this camera
+        // only clears the screen
+        RenderLayers::layer(
+            RenderLayers::TOTAL_LAYERS as u8 - 1,
+        ),
+        UiCameraConfig { show_ui: false },
+        LetterboxCamera,
+    ));
+}
+
+fn spawn_ui_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                priority: 10,
+                ..default()
+            },
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
+            ..default()
+        },
+        RenderLayers::layer(
+            RenderLayers::TOTAL_LAYERS as u8 - 1,
+        ),
+        UiCamera,
+    ));
+}
+
+/// Keeps the [`MainCamera`]'s viewport fit to its
+/// render target as the window is resized
+fn fit_camera_viewport(
+    windows: Res<Windows>,
+    settings: Res<CameraFitSettings>,
+    mut resized: EventReader<WindowResized>,
+    mut cameras: Query<&mut Camera, With<MainCamera>>,
+    mut last_target: Local<Option<RenderTarget>>,
+) {
+    let resized = resized.iter().count() > 0;
+    for mut camera in cameras.iter_mut() {
+        let retargeted =
+            last_target.as_ref() != Some(&camera.target);
+        if !resized && !settings.is_changed() && !retargeted
+        {
+            continue;
+        }
+        *last_target = Some(camera.target.clone());
+
+        let target = match &camera.target {
+            RenderTarget::Window(id) => {
+                windows.get(*id).map(|window| {
+                    UVec2::new(
+                        window.physical_width(),
+                        window.physical_height(),
+                    )
+                })
+            }
+            // pixel-perfect targets are already sized
+            // for exactly what the camera shows
+            RenderTarget::Image(_) => {
+                camera.viewport = None;
+                continue;
+            }
+        };
+
+        // minimized windows have no size
+        if let Some(target) =
+            target.filter(|size| size.x > 0 && size.y > 0)
+        {
+            let (physical_position, physical_size) =
+                fit_viewport(
+                    target,
+                    settings.aspect_ratio,
+                    settings.mode,
+                );
+            camera.viewport = Some(Viewport {
+                physical_position,
+                physical_size,
+                ..default()
+            });
+        }
+    }
+}
+
+fn apply_rect(
+    rect: Rect,
+    orthographic_projection: &mut OrthographicProjection,
+    camera_transform: &mut Transform,
+) {
+    orthographic_projection.scaling_mode =
+        ScalingMode::None;
+    orthographic_projection.left = 0.;
+    orthographic_projection.bottom = 0.;
+    orthographic_projection.right = rect.width();
+    orthographic_projection.top = rect.height();
+    camera_transform.translation.x = rect.min.x;
+    camera_transform.translation.y = rect.min.y;
+}
+
+/// Stops the rapier pipeline and any in-flight
+/// character controller movement while the camera
+/// slides between levels
+fn freeze_physics_during_transition(
+    transition: Option<Res<LevelTransition>>,
+    settings: Res<LevelTransitionSettings>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut controllers: Query<
+        &mut KinematicCharacterController,
+        With<Player>,
+    >,
+    mut was_frozen: Local<bool>,
+) {
+    let frozen =
+        transition.is_some() && settings.freeze_gameplay;
+    if frozen == *was_frozen {
+        return;
+    }
+    *was_frozen = frozen;
+
+    rapier_config.physics_pipeline_active = !frozen;
+    if frozen {
+        for mut controller in controllers.iter_mut() {
+            controller.translation = None;
+        }
+    }
+}
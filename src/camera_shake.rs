@@ -0,0 +1,241 @@
+use bevy::{
+    prelude::*, render::camera::OrthographicProjection,
+};
+
+use crate::{
+    camera::{
+        camera_fit_inside_current_level, CameraBounds,
+        MainCamera,
+    },
+    combat::DamageTaken,
+    components::Player,
+    movement::Landed,
+    pixel_perfect::PixelPerfectTarget,
+};
+
+pub struct CameraShakePlugin;
+
+impl Plugin for CameraShakePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraShakeSettings>()
+            .init_resource::<CameraShake>()
+            .add_event::<AddTrauma>()
+            .add_system(trauma_from_landings)
+            .add_system(trauma_from_damage)
+            .add_system(
+                add_trauma
+                    .after(trauma_from_landings)
+                    .after(trauma_from_damage),
+            )
+            .add_system(
+                shake_camera
+                    .after(add_trauma)
+                    .after(camera_fit_inside_current_level),
+            );
+    }
+}
+
+/// Adds to the [`CameraShake`]'s trauma.
+///
+/// Send this from anything that should rattle the
+/// screen: hard landings, hits, explosions.
+#[derive(Clone, Copy, Debug)]
+pub struct AddTrauma(pub f32);
+
+#[derive(Clone, Debug, Resource)]
+pub struct CameraShakeSettings {
+    /// Accessibility toggle, no shaking at all when
+    /// `false`
+    pub enabled: bool,
+    /// The offset at full trauma, in world pixels
+    pub max_offset: Vec2,
+    /// How much trauma is lost per second
+    pub decay: f32,
+    /// How quickly the shake changes direction
+    pub frequency: f32,
+    /// Landings from less than this height don't
+    /// shake the camera
+    pub hard_landing_height: f32,
+    /// Trauma from a player getting hurt
+    pub hurt_trauma: f32,
+    /// Trauma from a player hurting something else
+    pub hit_trauma: f32,
+}
+
+impl Default for CameraShakeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_offset: Vec2::new(24., 16.),
+            decay: 1.5,
+            frequency: 15.,
+            hard_landing_height: 300.,
+            hurt_trauma: 0.5,
+            hit_trauma: 0.2,
+        }
+    }
+}
+
+/// Trauma model screen shake: trauma is added by
+/// gameplay, decays over time, and the shake is the
+/// square of the trauma so small hits stay subtle.
+#[derive(Debug, Default, Resource)]
+pub struct CameraShake {
+    /// 0 is still, 1 is as much shake as there is
+    pub trauma: f32,
+}
+
+impl CameraShake {
+    pub fn shake(&self) -> f32 {
+        self.trauma * self.trauma
+    }
+}
+
+fn trauma_from_landings(
+    settings: Res<CameraShakeSettings>,
+    mut landings: EventReader<Landed>,
+    mut trauma: EventWriter<AddTrauma>,
+) {
+    for landing in landings.iter() {
+        if landing.fall_height
+            > settings.hard_landing_height
+        {
+            trauma.send(AddTrauma(
+                (landing.fall_height
+                    / settings.hard_landing_height
+                    - 1.)
+                    .clamp(0.2, 0.6),
+            ));
+        }
+    }
+}
+
+fn trauma_from_damage(
+    settings: Res<CameraShakeSettings>,
+    mut damage: EventReader<DamageTaken>,
+    players: Query<(), With<Player>>,
+    mut trauma: EventWriter<AddTrauma>,
+) {
+    for damage in damage.iter() {
+        if players.contains(damage.target) {
+            trauma.send(AddTrauma(settings.hurt_trauma));
+        } else if players.contains(damage.source) {
+            trauma.send(AddTrauma(settings.hit_trauma));
+        }
+    }
+}
+
+fn add_trauma(
+    time: Res<Time>,
+    settings: Res<CameraShakeSettings>,
+    mut shake: ResMut<CameraShake>,
+    mut events: EventReader<AddTrauma>,
+) {
+    let mut trauma = (shake.trauma
+        - settings.decay * time.delta_seconds())
+    .max(0.);
+    for AddTrauma(amount) in events.iter() {
+        trauma += amount;
+    }
+    // avoid triggering change detection every frame
+    // once the shake has settled
+    if trauma != shake.trauma {
+        shake.trauma = trauma.min(1.);
+    }
+}
+
+/// Offsets the camera on top of the level fitting,
+/// without letting it show anything outside of the
+/// level
+fn shake_camera(
+    time: Res<Time>,
+    settings: Res<CameraShakeSettings>,
+    shake: Res<CameraShake>,
+    bounds: Res<CameraBounds>,
+    pixel_target: Option<Res<PixelPerfectTarget>>,
+    mut cameras: Query<
+        (&OrthographicProjection, &mut Transform),
+        With<MainCamera>,
+    >,
+) {
+    if !settings.enabled || shake.trauma <= 0. {
+        return;
+    }
+
+    let t = time.elapsed_seconds() * settings.frequency;
+    let mut offset = settings.max_offset
+        * shake.shake()
+        * Vec2::new(noise(0, t), noise(1, t));
+    if pixel_target.is_some() {
+        // stay on whole pixels
+        offset = offset.round();
+    }
+
+    for (projection, mut transform) in cameras.iter_mut() {
+        let min = transform.translation.truncate()
+            + Vec2::new(projection.left, projection.bottom);
+        let size = Vec2::new(
+            projection.right - projection.left,
+            projection.top - projection.bottom,
+        );
+
+        let shaken = match bounds.0 {
+            Some(bounds) => Vec2::new(
+                shake_axis(
+                    min.x,
+                    offset.x,
+                    bounds.min.x,
+                    bounds.max.x - size.x,
+                ),
+                shake_axis(
+                    min.y,
+                    offset.y,
+                    bounds.min.y,
+                    bounds.max.y - size.y,
+                ),
+            ),
+            None => min + offset,
+        };
+
+        transform.translation.x += shaken.x - min.x;
+        transform.translation.y += shaken.y - min.y;
+    }
+}
+
+/// Moves `min` by `offset` but not past the level's
+/// edges. Views bigger than the level, or already
+/// outside of it mid-transition, aren't pushed any
+/// further out.
+fn shake_axis(
+    min: f32,
+    offset: f32,
+    lowest: f32,
+    highest: f32,
+) -> f32 {
+    if highest < lowest {
+        min
+    } else {
+        (min + offset)
+            .clamp(lowest.min(min), highest.max(min))
+    }
+}
+
+/// Smooth 1d value noise in -1..1
+fn noise(seed: u32, t: f32) -> f32 {
+    let i = t.floor();
+    let f = t - i;
+    let a = hash(seed, i as i32);
+    let b = hash(seed, i as i32 + 1);
+    a + (b - a) * f * f * (3. - 2. * f)
+}
+
+fn hash(seed: u32, n: i32) -> f32 {
+    let mut x = (n as u32).wrapping_mul(0x9E37_79B1)
+        ^ seed.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x2C1B_3C6D);
+    x ^= x >> 12;
+    x = x.wrapping_mul(0x297A_2D39);
+    x ^= x >> 15;
+    x as f32 / u32::MAX as f32 * 2. - 1.
+}
@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+
+use bevy::{math::Rect, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::{Checkpoint, Player},
+    GameState,
+};
+
+pub struct CheckpointPlugin;
+
+impl Plugin for CheckpointPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReachedCheckpoints>()
+            .add_event::<CheckpointReached>()
+            .add_system(
+                reach_checkpoints
+                    .run_in_state(GameState::Playing),
+            );
+    }
+}
+
+/// Sent the first time any player touches a
+/// checkpoint
+#[derive(Clone, Debug)]
+pub struct CheckpointReached {
+    pub player: Entity,
+    pub checkpoint: Entity,
+    /// The checkpoint's LDtk iid
+    pub iid: String,
+}
+
+/// The LDtk iids of every checkpoint reached so far
+#[derive(
+    Clone, Debug, Default, Resource, Serialize, Deserialize,
+)]
+pub struct ReachedCheckpoints(pub HashSet<String>);
+
+fn reach_checkpoints(
+    mut reached: ResMut<ReachedCheckpoints>,
+    mut events: EventWriter<CheckpointReached>,
+    players: Query<
+        (Entity, &GlobalTransform),
+        With<Player>,
+    >,
+    checkpoints: Query<
+        (Entity, &EntityInstance, &GlobalTransform),
+        With<Checkpoint>,
+    >,
+) {
+    // roughly the player's capsule
+    let reach = Vec2::new(12., 24.);
+    for (checkpoint, instance, transform) in
+        checkpoints.iter()
+    {
+        if reached.0.contains(&instance.iid) {
+            continue;
+        }
+        let area = Rect::from_center_size(
+            transform.translation().truncate(),
+            IVec2::new(instance.width, instance.height)
+                .as_vec2()
+                + reach * 2.,
+        );
+        if let Some((player, _)) =
+            players.iter().find(|(_, player)| {
+                area.contains(
+                    player.translation().truncate(),
+                )
+            })
+        {
+            reached.0.insert(instance.iid.clone());
+            events.send(CheckpointReached {
+                player,
+                checkpoint,
+                iid: instance.iid.clone(),
+            });
+        }
+    }
+}
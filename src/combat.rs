@@ -0,0 +1,637 @@
+use std::{collections::HashSet, time::Duration};
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    actions::PlatformerAction,
+    camera::{
+        gameplay_frozen, LevelTransition,
+        LevelTransitionSettings,
+    },
+    components::{
+        Enemy, EnemyStats, HealMeter, Health, Player,
+    },
+    enemy::{EnemyBrain, State as EnemyState},
+    juice::PlayerVisual,
+    movement::{
+        PlayerSimulation, PlayerState, SimulationClock,
+        State, SIMULATION, SIMULATION_GAMEPLAY, TIMESTEP,
+    },
+    settings::GameSpeed,
+    GameState,
+};
+
+/// How long a hurt player can't be hurt again
+const PLAYER_INVULNERABILITY: Duration =
+    Duration::from_secs(1);
+/// How often an invulnerable player blinks
+const BLINK: Duration = Duration::from_millis(100);
+/// How long a knockback pushes for
+const KNOCKBACK_TIME: Duration = Duration::from_millis(200);
+/// How hard touching an enemy pushes a player, away
+/// and up, in px/s
+const CONTACT_KNOCKBACK: Vec2 = Vec2::new(250., 150.);
+const STOMP_DAMAGE: u32 = 1;
+/// How far an enemy's [`ContactArea`] reaches past
+/// its body, so it's touched before the body blocks
+/// the player
+const CONTACT_MARGIN: f32 = 3.;
+/// How far a player's feet can be below an enemy's
+/// top and still count as landing on it
+const STOMP_LEEWAY: f32 = 2.;
+/// How many frames a player landing a hit freezes
+/// the game for
+const HIT_STOP_FRAMES: u32 = 4;
+/// How fast `Time` runs during a hit-stop, so
+/// animations and particles slow to a crawl
+const HIT_STOP_SPEED: f32 = 0.05;
+
+/// Players and enemies hurting each other. Anything
+/// that deals damage sends [`DealDamage`]; anything
+/// that reacts to it reads [`DamageTaken`] or
+/// [`Killed`].
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EnemyContacts>()
+            .init_resource::<HitStop>()
+            .add_event::<DealDamage>()
+            .add_event::<DamageTaken>()
+            .add_event::<Killed>()
+            .add_event::<Healed>()
+            .add_system(add_contact_areas)
+            .add_system(
+                track_contacts
+                    .run_in_state(GameState::Playing)
+                    .label(CombatSystem::TrackContacts),
+            )
+            .add_system(
+                contact_damage
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .after(CombatSystem::TrackContacts)
+                    .label(DamageSources),
+            )
+            .add_system(
+                apply_damage
+                    .run_in_state(GameState::Playing)
+                    .label(CombatSystem::ApplyDamage)
+                    .after(DamageSources),
+            )
+            .add_system(
+                remove_dead_enemies
+                    .run_in_state(GameState::Playing)
+                    .after(CombatSystem::ApplyDamage),
+            )
+            .add_system(
+                charge_heal_meters
+                    .run_in_state(GameState::Playing)
+                    .after(CombatSystem::ApplyDamage),
+            )
+            .add_system(
+                heal.run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen),
+            )
+            .add_system(
+                blink_invulnerable
+                    .run_in_state(GameState::Playing),
+            )
+            // at the very end, so every system this
+            // frame agreed on whether it was frozen
+            .add_system_to_stage(
+                CoreStage::Last,
+                run_hit_stop
+                    .run_in_state(GameState::Playing),
+            )
+            .add_fixed_timestep_system(
+                SIMULATION,
+                SIMULATION_GAMEPLAY,
+                knock_back_players
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .after(PlayerSimulation),
+            );
+    }
+}
+
+/// Systems sending [`DealDamage`], which is applied
+/// after them in the same frame
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+pub struct DamageSources;
+
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+pub enum CombatSystem {
+    TrackContacts,
+    ApplyDamage,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DamageKind {
+    /// Touching an enemy
+    Contact,
+    /// Landing on an enemy
+    Stomp,
+    /// A player's sword, see [`crate::attack`]
+    Slash,
+    /// See [`crate::projectile`]
+    Projectile,
+}
+
+/// Asks for `target` to be hurt. Ignored while it's
+/// [`Invulnerable`].
+#[derive(Clone, Copy, Debug)]
+pub struct DealDamage {
+    pub target: Entity,
+    pub source: Entity,
+    pub amount: u32,
+    pub kind: DamageKind,
+    /// Pushes the target, in px/s
+    pub knockback: Vec2,
+}
+
+/// Sent once damage has come off a target's
+/// [`Health`]
+#[derive(Clone, Copy, Debug)]
+pub struct DamageTaken {
+    pub target: Entity,
+    pub source: Entity,
+    pub amount: u32,
+    pub kind: DamageKind,
+    /// The health left
+    pub remaining: u32,
+}
+
+/// Sent when a target's [`Health`] runs out. Dead
+/// enemies are despawned, what happens to players is
+/// up to whoever listens.
+#[derive(Clone, Copy, Debug)]
+pub struct Killed {
+    pub target: Entity,
+    pub source: Entity,
+    pub kind: DamageKind,
+    /// Where the target died, as it may be gone by
+    /// the time this is read
+    pub position: Vec2,
+}
+
+/// Sent when a player gets health back, from their
+/// [`HealMeter`] or a potion
+#[derive(Clone, Copy, Debug)]
+pub struct Healed {
+    pub target: Entity,
+    pub amount: u32,
+    /// The health after healing
+    pub current: u32,
+}
+
+/// Can't be damaged until the simulation reaches
+/// `until`
+#[derive(Clone, Copy, Debug, Component)]
+pub struct Invulnerable {
+    pub until: Duration,
+}
+
+/// Pushes an entity around for a moment, overriding
+/// its own movement
+#[derive(Clone, Copy, Debug, Component)]
+pub struct Knockback {
+    /// In px/s
+    pub velocity: Vec2,
+    pub until: Duration,
+}
+
+impl Knockback {
+    /// The push at `now`, if it's still going
+    pub fn velocity_at(
+        &self,
+        now: Duration,
+    ) -> Option<Vec2> {
+        (now < self.until).then_some(self.velocity)
+    }
+}
+
+/// Frames left of the freeze after a player lands
+/// a hit. Gameplay holds still while it's running,
+/// see [`crate::camera::gameplay_frozen`].
+#[derive(Debug, Default, Resource)]
+pub struct HitStop {
+    pub frames: u32,
+}
+
+/// The sensor around an enemy that hurts players
+/// touching it
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+pub struct ContactArea;
+
+/// Which players each [`ContactArea`] is touching,
+/// as `(area, player)`
+#[derive(Debug, Default, Resource)]
+struct EnemyContacts(HashSet<(Entity, Entity)>);
+
+fn add_contact_areas(
+    mut commands: Commands,
+    enemies: Query<(Entity, &Collider), Added<Enemy>>,
+) {
+    for (enemy, collider) in enemies.iter() {
+        let area = match collider.as_cuboid() {
+            Some(cuboid) => {
+                let half_extents = cuboid.half_extents()
+                    + Vec2::splat(CONTACT_MARGIN);
+                Collider::cuboid(
+                    half_extents.x,
+                    half_extents.y,
+                )
+            }
+            None => collider.clone(),
+        };
+        commands.entity(enemy).with_children(|parent| {
+            parent.spawn((
+                area,
+                Sensor,
+                // both enemies and players are
+                // kinematic, which rapier ignores
+                // unless asked
+                ActiveCollisionTypes::default()
+                    | ActiveCollisionTypes::KINEMATIC_KINEMATIC,
+                ActiveEvents::COLLISION_EVENTS,
+                ContactArea,
+                TransformBundle::default(),
+            ));
+        });
+    }
+}
+
+fn track_contacts(
+    mut collisions: EventReader<CollisionEvent>,
+    areas: Query<(), With<ContactArea>>,
+    players: Query<(), With<Player>>,
+    mut contacts: ResMut<EnemyContacts>,
+) {
+    for collision in collisions.iter() {
+        let (a, b, started) = match collision {
+            CollisionEvent::Started(a, b, _) => {
+                (*a, *b, true)
+            }
+            CollisionEvent::Stopped(a, b, _) => {
+                (*a, *b, false)
+            }
+        };
+        let pair = if areas.contains(a)
+            && players.contains(b)
+        {
+            (a, b)
+        } else if areas.contains(b) && players.contains(a) {
+            (b, a)
+        } else {
+            continue;
+        };
+        if started {
+            contacts.0.insert(pair);
+        } else {
+            contacts.0.remove(&pair);
+        }
+    }
+}
+
+/// Players falling onto an enemy stomp it and bounce
+/// off, any other touch hurts the player
+fn contact_damage(
+    clock: Res<SimulationClock>,
+    mut contacts: ResMut<EnemyContacts>,
+    areas: Query<&Parent, With<ContactArea>>,
+    enemies: Query<(
+        &GlobalTransform,
+        &Collider,
+        &EnemyStats,
+        &EnemyBrain,
+    )>,
+    mut players: Query<(
+        &GlobalTransform,
+        &Collider,
+        &mut PlayerState,
+    )>,
+    mut damage: EventWriter<DealDamage>,
+) {
+    // despawned colliders don't always say they've
+    // stopped touching
+    contacts.0.retain(|(area, player)| {
+        areas.contains(*area) && players.contains(*player)
+    });
+
+    for &(area, player) in contacts.0.iter() {
+        let enemy = match areas.get(area) {
+            Ok(parent) => parent.get(),
+            Err(_) => continue,
+        };
+        let (enemy_transform, enemy_body, stats, brain) =
+            match enemies.get(enemy) {
+                Ok(enemy) => enemy,
+                Err(_) => continue,
+            };
+        let (player_transform, player_body, mut state) =
+            match players.get_mut(player) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+        let enemy_position =
+            enemy_transform.translation().truncate();
+        let player_position =
+            player_transform.translation().truncate();
+
+        let feet =
+            player_position.y - half_height(player_body);
+        let top =
+            enemy_position.y + half_height(enemy_body);
+
+        if feet >= top - STOMP_LEEWAY {
+            if let State::Falling {} = state.state() {
+                state.bounce(clock.elapsed());
+                damage.send(DealDamage {
+                    target: enemy,
+                    source: player,
+                    amount: STOMP_DAMAGE,
+                    kind: DamageKind::Stomp,
+                    knockback: Vec2::ZERO,
+                });
+            }
+            // standing on it or springing off it
+            // doesn't hurt
+            continue;
+        }
+        if let EnemyState::Stunned {} = brain.state() {
+            continue;
+        }
+        let away =
+            (player_position.x - enemy_position.x).signum();
+        damage.send(DealDamage {
+            target: player,
+            source: enemy,
+            amount: stats.contact_damage,
+            kind: DamageKind::Contact,
+            knockback: CONTACT_KNOCKBACK
+                * Vec2::new(away, 1.),
+        });
+    }
+}
+
+/// How far a collider reaches above and below its
+/// center
+fn half_height(collider: &Collider) -> f32 {
+    collider.raw.compute_local_aabb().half_extents().y
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    mut requests: EventReader<DealDamage>,
+    mut targets: Query<(
+        &mut Health,
+        &GlobalTransform,
+        Option<&Invulnerable>,
+        Option<&mut EnemyBrain>,
+        Option<&Player>,
+    )>,
+    mut taken: EventWriter<DamageTaken>,
+    mut killed: EventWriter<Killed>,
+) {
+    let now = clock.elapsed();
+    // invulnerability given this frame, which
+    // commands haven't inserted yet
+    let mut shielded = HashSet::new();
+    for request in requests.iter() {
+        let (
+            mut health,
+            transform,
+            invulnerable,
+            brain,
+            player,
+        ) = match targets.get_mut(request.target) {
+            Ok(target) => target,
+            Err(_) => continue,
+        };
+        if health.current == 0
+            || shielded.contains(&request.target)
+            || invulnerable.is_some_and(|invulnerable| {
+                now < invulnerable.until
+            })
+        {
+            continue;
+        }
+
+        health.current =
+            health.current.saturating_sub(request.amount);
+        taken.send(DamageTaken {
+            target: request.target,
+            source: request.source,
+            amount: request.amount,
+            kind: request.kind,
+            remaining: health.current,
+        });
+        if health.current == 0 {
+            killed.send(Killed {
+                target: request.target,
+                source: request.source,
+                kind: request.kind,
+                position: transform
+                    .translation()
+                    .truncate(),
+            });
+            continue;
+        }
+
+        if let Some(mut brain) = brain {
+            brain.stun(now);
+        }
+        if player.is_some() {
+            shielded.insert(request.target);
+            commands.entity(request.target).insert(
+                Invulnerable {
+                    until: now + PLAYER_INVULNERABILITY,
+                },
+            );
+        }
+        if request.knockback != Vec2::ZERO {
+            commands.entity(request.target).insert(
+                Knockback {
+                    velocity: request.knockback,
+                    until: now + KNOCKBACK_TIME,
+                },
+            );
+        }
+    }
+}
+
+/// Every hit a player lands charges their
+/// [`HealMeter`]
+fn charge_heal_meters(
+    mut taken: EventReader<DamageTaken>,
+    mut meters: Query<&mut HealMeter>,
+) {
+    for event in taken.iter() {
+        if event.source == event.target {
+            continue;
+        }
+        if let Ok(mut meter) = meters.get_mut(event.source)
+        {
+            meter.charge =
+                (meter.charge + 1).min(meter.max);
+        }
+    }
+}
+
+/// Spends a [`HealMeter`]'s charge on one health,
+/// when there's enough of it and health to restore
+fn heal(
+    mut players: Query<(
+        Entity,
+        &ActionState<PlatformerAction>,
+        &mut Health,
+        &mut HealMeter,
+    )>,
+    mut healed: EventWriter<Healed>,
+) {
+    for (entity, action_state, mut health, mut meter) in
+        players.iter_mut()
+    {
+        if !action_state
+            .just_pressed(PlatformerAction::Heal)
+            || meter.charge < meter.cost
+            || health.current == 0
+            || health.current >= health.max
+        {
+            continue;
+        }
+        meter.charge -= meter.cost;
+        health.current += 1;
+        healed.send(Healed {
+            target: entity,
+            amount: 1,
+            current: health.current,
+        });
+    }
+}
+
+fn remove_dead_enemies(
+    mut commands: Commands,
+    mut killed: EventReader<Killed>,
+    enemies: Query<(), With<Enemy>>,
+) {
+    for event in killed.iter() {
+        if enemies.contains(event.target) {
+            commands
+                .entity(event.target)
+                .despawn_recursive();
+        }
+    }
+}
+
+/// Knocked back players go where they're pushed
+/// instead of where they're steering
+fn knock_back_players(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    mut players: Query<(
+        Entity,
+        &Knockback,
+        &mut KinematicCharacterController,
+    )>,
+) {
+    for (entity, knockback, mut controller) in
+        players.iter_mut()
+    {
+        match knockback.velocity_at(clock.elapsed()) {
+            Some(velocity) => {
+                controller.translation =
+                    Some(velocity * TIMESTEP);
+            }
+            None => {
+                commands
+                    .entity(entity)
+                    .remove::<Knockback>();
+            }
+        }
+    }
+}
+
+/// Flickers invulnerable players, and lets them be
+/// hurt again once it wears off
+fn blink_invulnerable(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    targets: Query<(Entity, &Invulnerable)>,
+    mut visuals: Query<
+        (&Parent, &mut Visibility),
+        With<PlayerVisual>,
+    >,
+) {
+    let now = clock.elapsed();
+    for (parent, mut visibility) in visuals.iter_mut() {
+        visibility.is_visible =
+            match targets.get(parent.get()) {
+                Ok((_, invulnerable))
+                    if now < invulnerable.until =>
+                {
+                    (now.as_millis() / BLINK.as_millis())
+                        .is_multiple_of(2)
+                }
+                _ => true,
+            };
+    }
+    for (entity, invulnerable) in targets.iter() {
+        if now >= invulnerable.until {
+            commands
+                .entity(entity)
+                .remove::<Invulnerable>();
+        }
+    }
+}
+
+/// Starts a hit-stop when a player hurts something,
+/// and counts it down
+fn run_hit_stop(
+    mut time: ResMut<Time>,
+    mut hit_stop: ResMut<HitStop>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut damage: EventReader<DamageTaken>,
+    players: Query<(), With<Player>>,
+    mut controllers: Query<
+        &mut KinematicCharacterController,
+        With<Player>,
+    >,
+    transition: Option<Res<LevelTransition>>,
+    settings: Option<Res<LevelTransitionSettings>>,
+    game_speed: Res<GameSpeed>,
+) {
+    let was_stopped = hit_stop.frames > 0;
+    hit_stop.frames = hit_stop.frames.saturating_sub(1);
+    if damage
+        .iter()
+        .any(|damage| players.contains(damage.source))
+    {
+        hit_stop.frames = HIT_STOP_FRAMES;
+    }
+
+    if hit_stop.frames > 0 {
+        time.set_relative_speed(
+            HIT_STOP_SPEED * game_speed.speed,
+        );
+        rapier_config.physics_pipeline_active = false;
+        for mut controller in controllers.iter_mut() {
+            controller.translation = None;
+        }
+    } else if was_stopped {
+        time.set_relative_speed(game_speed.speed);
+        rapier_config.physics_pipeline_active =
+            !gameplay_frozen(transition, settings, None);
+    }
+}
@@ -1,15 +1,20 @@
 use crate::{
-    actions::PlatformerAction, movement::PlayerState,
+    actions::PlatformerAction,
+    bindings::InputProfiles,
+    enemy::{EnemyBrain, EnemyPath},
+    items::{EquipSlot, Item},
+    movement::{Dash, PlayerState, SimulationInput},
 };
-use bevy::prelude::*;
+use bevy::{math::Rect, prelude::*};
 use bevy_ecs_ldtk::{
     prelude::*,
     utils::ldtk_pixel_coords_to_translation_pivoted,
 };
 use bevy_rapier2d::prelude::*;
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use std::collections::HashSet;
+use std::{collections::HashSet, time::Duration};
 
 #[derive(Clone, Default, Bundle, LdtkIntCell)]
 pub struct ColliderBundle {
@@ -88,98 +93,58 @@ impl From<IntGridCell> for ColliderBundle {
 pub struct PlayerInput {
     #[bundle]
     input: InputManagerBundle<PlatformerAction>,
+    simulation: SimulationInput,
 }
 impl Default for PlayerInput {
     fn default() -> Self {
-        use PlatformerAction::*;
-
-        let mut input_map = InputMap::default();
-
-        // basic movement
-        input_map.insert(KeyCode::W, Up);
-        input_map.insert(GamepadButtonType::DPadUp, Up);
-
-        input_map.insert(KeyCode::S, Down);
-        input_map.insert(GamepadButtonType::DPadDown, Down);
-
-        input_map.insert(KeyCode::A, Left);
-        input_map.insert(GamepadButtonType::DPadLeft, Left);
-
-        input_map.insert(
-            SingleAxis::symmetric(
-                GamepadAxisType::LeftStickX,
-                0.1,
-            ),
-            Horizontal,
-        );
-
-        input_map.insert(KeyCode::D, Right);
-        input_map
-            .insert(GamepadButtonType::DPadRight, Right);
-
-        // Jump
-        input_map
-            .insert(KeyCode::Space, PlatformerAction::Jump);
-        input_map.insert(
-            GamepadButtonType::South,
-            PlatformerAction::Jump,
-        );
-
-        input_map
-            .insert(KeyCode::E, PlatformerAction::Dash);
-        input_map.insert(
-            GamepadButtonType::RightTrigger2,
-            PlatformerAction::Dash,
-        );
-
-        input_map.insert(
-            KeyCode::Return,
-            PlatformerAction::Pause,
-        );
-        input_map.insert(
-            GamepadButtonType::Start,
-            PlatformerAction::Pause,
-        );
-
-        input_map
-            .insert(KeyCode::I, PlatformerAction::Menus);
-        input_map.insert(
-            GamepadButtonType::Select,
-            PlatformerAction::Menus,
-        );
-        input_map.set_gamepad(Gamepad { id: 0 });
+        // replaced by the player slot's bindings once
+        // the player spawns, see
+        // `gamepad::apply_slot_bindings`
+        let input_map =
+            InputProfiles::default().input_map();
         Self {
             input: InputManagerBundle::<PlatformerAction> {
                 input_map,
                 ..Default::default()
             },
+            simulation: SimulationInput::default(),
         }
     }
 }
 
 #[derive(
-    Clone, Component, Debug, Eq, Default, PartialEq,
+    Clone,
+    Component,
+    Debug,
+    Eq,
+    Default,
+    PartialEq,
+    Serialize,
+    Deserialize,
 )]
-pub struct Items(Vec<String>);
+pub struct Items(pub Vec<Item>);
 
 impl From<EntityInstance> for Items {
     fn from(entity_instance: EntityInstance) -> Self {
-        let mut items: Vec<String> = vec![];
+        let mut items: Vec<Item> = vec![];
 
         if let Some(field_instance) = entity_instance
             .field_instances
             .iter()
             .find(|f| f.identifier == *"items")
         {
-            // convert &String to String which returns
-            // vec![String::from("Knife"),
-            // String::from("Boot")]
             items = match &field_instance.value {
                 FieldValue::Enums(v) => v
                     .iter()
                     .flatten()
-                    .map(|s| s.into())
-                    .collect::<Vec<String>>(),
+                    .filter_map(|s| match s.parse() {
+                        Ok(item) => Some(item),
+                        Err(error) => {
+                            warn!("{error}");
+                            None
+                        }
+                    })
+                    .collect::<Vec<Item>>(),
                 _ => vec![],
             };
         }
@@ -188,6 +153,117 @@ impl From<EntityInstance> for Items {
     }
 }
 
+/// The items from a player's [`Items`] they have
+/// equipped
+#[derive(
+    Clone,
+    Component,
+    Debug,
+    Eq,
+    Default,
+    PartialEq,
+    Serialize,
+    Deserialize,
+)]
+pub struct Equipment {
+    pub weapon: Option<Item>,
+    pub feet: Option<Item>,
+}
+
+impl Equipment {
+    pub fn slot_mut(
+        &mut self,
+        slot: EquipSlot,
+    ) -> &mut Option<Item> {
+        match slot {
+            EquipSlot::Weapon => &mut self.weapon,
+            EquipSlot::Feet => &mut self.feet,
+        }
+    }
+
+    pub fn is_equipped(&self, item: Item) -> bool {
+        self.weapon == Some(item) || self.feet == Some(item)
+    }
+
+    /// Equips `item`, or takes it off if it was
+    /// already equipped
+    pub fn toggle(&mut self, slot: EquipSlot, item: Item) {
+        let equipped = self.slot_mut(slot);
+        *equipped = if *equipped == Some(item) {
+            None
+        } else {
+            Some(item)
+        };
+    }
+}
+
+#[derive(
+    Copy,
+    Clone,
+    Eq,
+    PartialEq,
+    Debug,
+    Component,
+    Serialize,
+    Deserialize,
+)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self { current: 5, max: 5 }
+    }
+}
+
+/// Full health, from the entity's "health" field if
+/// it has one
+impl From<EntityInstance> for Health {
+    fn from(entity_instance: EntityInstance) -> Self {
+        entity_instance
+            .field_instances
+            .iter()
+            .find_map(|field_instance| {
+                match (
+                    field_instance.identifier.as_ref(),
+                    &field_instance.value,
+                ) {
+                    (
+                        "health",
+                        FieldValue::Int(Some(v)),
+                    ) => {
+                        let max = (*v).max(1) as u32;
+                        Some(Health { current: max, max })
+                    }
+                    _ => None,
+                }
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// What a player spends on [`PlatformerAction::Heal`],
+/// charged by landing hits
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Component)]
+pub struct HealMeter {
+    pub charge: u32,
+    pub max: u32,
+    /// How much charge a heal takes
+    pub cost: u32,
+}
+
+impl Default for HealMeter {
+    fn default() -> Self {
+        Self {
+            charge: 0,
+            max: 9,
+            cost: 3,
+        }
+    }
+}
+
 #[derive(
     Copy, Clone, Eq, PartialEq, Debug, Default, Component,
 )]
@@ -230,6 +306,10 @@ pub struct PlayerBundle {
     // From<EntityInstance>
     #[from_entity_instance]
     items: Items,
+    pub equipment: Equipment,
+    pub health: Health,
+    pub heal_meter: HealMeter,
+    pub dash: Dash,
 
     // The whole EntityInstance can be stored directly as
     // an EntityInstance component
@@ -240,6 +320,32 @@ pub struct PlayerBundle {
     pub input: PlayerInput,
 }
 
+impl PlayerBundle {
+    /// Builds the same bundle LDtk spawns the player
+    /// with, for players that join on top of the one
+    /// placed in the level
+    pub fn from_entity_instance(
+        entity_instance: &EntityInstance,
+    ) -> Self {
+        Self {
+            collider_bundle: entity_instance.clone().into(),
+            worldly: Worldly::from_entity_info(
+                entity_instance,
+            ),
+            items: entity_instance.clone().into(),
+            entity_instance: entity_instance.clone(),
+            ..default()
+        }
+    }
+}
+
+/// Which of the joined players this is, indexing
+/// into `gamepad::PlayerSlots`
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+pub struct PlayerSlot(pub usize);
+
 #[derive(
     Copy, Clone, Eq, PartialEq, Debug, Default, Component,
 )]
@@ -250,6 +356,13 @@ pub struct WallBundle {
     wall: Wall,
 }
 
+/// On a level once `systems::spawn_wall_collision`
+/// has given its walls colliders
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+pub struct WallColliders;
+
 #[derive(
     Copy, Clone, Eq, PartialEq, Debug, Default, Component,
 )]
@@ -306,31 +419,29 @@ impl LdtkEntity for Patrol {
         if let FieldValue::Points(ldtk_points) =
             &ldtk_patrol.value
         {
-            for ldtk_point in ldtk_points {
-                if let Some(ldtk_point) = ldtk_point {
-                    // The +1 is necessary here due to the
-                    // pivot of the entities in the sample
-                    // file.
-                    // The patrols set up in the file look
-                    // flat and grounded,
-                    // but technically they're not if you
-                    // consider the pivot,
-                    // which is at the bottom-center for the
-                    // skulls.
-                    let pixel_coords = (ldtk_point
-                        .as_vec2()
-                        + Vec2::new(0.5, 1.))
-                        * Vec2::splat(
-                            layer_instance.grid_size as f32,
-                        );
-
-                    points.push(ldtk_pixel_coords_to_translation_pivoted(
-                        pixel_coords.as_ivec2(),
-                        layer_instance.c_hei * layer_instance.grid_size,
-                        IVec2::new(entity_instance.width, entity_instance.height),
-                        entity_instance.pivot,
-                    ));
-                }
+            for ldtk_point in ldtk_points.iter().flatten() {
+                // The +1 is necessary here due to the
+                // pivot of the entities in the sample
+                // file.
+                // The patrols set up in the file look
+                // flat and grounded,
+                // but technically they're not if you
+                // consider the pivot,
+                // which is at the bottom-center for the
+                // skulls.
+                let pixel_coords = (ldtk_point
+                    .as_vec2()
+                    + Vec2::new(0.5, 1.))
+                    * Vec2::splat(
+                        layer_instance.grid_size as f32,
+                    );
+
+                points.push(ldtk_pixel_coords_to_translation_pivoted(
+                    pixel_coords.as_ivec2(),
+                    layer_instance.c_hei * layer_instance.grid_size,
+                    IVec2::new(entity_instance.width, entity_instance.height),
+                    entity_instance.pivot,
+                ));
             }
         }
 
@@ -342,7 +453,84 @@ impl LdtkEntity for Patrol {
     }
 }
 
-#[derive(Clone, Default, Bundle, LdtkEntity)]
+/// How an enemy type behaves, from its LDtk fields
+#[derive(Clone, PartialEq, Debug, Component)]
+pub struct EnemyStats {
+    /// How close a player has to be to be noticed,
+    /// in world pixels
+    pub aggro_range: f32,
+    /// How close a player has to be to be attacked
+    pub attack_range: f32,
+    /// Patrolling speed, in px/s
+    pub speed: f32,
+    pub chase_speed: f32,
+    /// How long a stun lasts, in seconds
+    pub stun_time: f32,
+    /// How much touching the enemy hurts
+    pub contact_damage: u32,
+    /// Ground enemies find their way with
+    /// [`crate::navigation`], flying ones go straight
+    pub walks: bool,
+    /// How high a walking enemy can jump, in world
+    /// pixels
+    pub jump_height: f32,
+}
+
+impl Default for EnemyStats {
+    fn default() -> Self {
+        Self {
+            aggro_range: 120.,
+            attack_range: 20.,
+            speed: 75.,
+            chase_speed: 110.,
+            stun_time: 1.5,
+            contact_damage: 1,
+            walks: false,
+            jump_height: 48.,
+        }
+    }
+}
+
+impl From<EntityInstance> for EnemyStats {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut stats = EnemyStats::default();
+
+        for field_instance in
+            &entity_instance.field_instances
+        {
+            if let ("walks", FieldValue::Bool(walks)) = (
+                field_instance.identifier.as_ref(),
+                &field_instance.value,
+            ) {
+                stats.walks = *walks;
+            }
+            let value = match &field_instance.value {
+                FieldValue::Float(Some(v)) => *v,
+                FieldValue::Int(Some(v)) => *v as f32,
+                _ => continue,
+            };
+            match field_instance.identifier.as_ref() {
+                "aggro_range" => stats.aggro_range = value,
+                "attack_range" => {
+                    stats.attack_range = value
+                }
+                "speed" => stats.speed = value,
+                "chase_speed" => stats.chase_speed = value,
+                "stun_time" => stats.stun_time = value,
+                "jump_height" => stats.jump_height = value,
+                "damage" => {
+                    stats.contact_damage =
+                        value.max(0.) as u32
+                }
+                _ => {}
+            }
+        }
+
+        stats
+    }
+}
+
+#[derive(Default, Bundle, LdtkEntity)]
 pub struct MobBundle {
     #[sprite_sheet_bundle]
     #[bundle]
@@ -353,6 +541,105 @@ pub struct MobBundle {
     pub enemy: Enemy,
     #[ldtk_entity]
     pub patrol: Patrol,
+    #[from_entity_instance]
+    pub stats: EnemyStats,
+    pub brain: EnemyBrain,
+    pub path: EnemyPath,
+    #[from_entity_instance]
+    pub health: Health,
+}
+
+/// Fires projectiles, see [`crate::projectile`]
+#[derive(Clone, Debug, Component)]
+pub struct Turret {
+    /// Seconds between shots
+    pub interval: f32,
+    /// Only fires at players it can see within
+    /// `range`, instead of along `angle`
+    pub aim_at_player: bool,
+    /// Degrees counterclockwise from the right
+    pub angle: f32,
+    pub range: f32,
+    /// In px/s
+    pub speed: f32,
+    /// In px/s²
+    pub gravity: f32,
+    /// How long a shot flies, in seconds
+    pub lifetime: f32,
+    pub damage: u32,
+    /// How many targets a shot goes through before
+    /// it's spent
+    pub pierce: u32,
+    /// When it fires next, in simulated time
+    pub next_shot: Duration,
+}
+
+impl Default for Turret {
+    fn default() -> Self {
+        Self {
+            interval: 2.,
+            aim_at_player: false,
+            angle: 0.,
+            range: 200.,
+            speed: 160.,
+            gravity: 0.,
+            lifetime: 3.,
+            damage: 1,
+            pierce: 0,
+            next_shot: Duration::ZERO,
+        }
+    }
+}
+
+impl From<EntityInstance> for Turret {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut turret = Turret::default();
+
+        for field_instance in
+            &entity_instance.field_instances
+        {
+            if let (
+                "aim_at_player",
+                FieldValue::Bool(aim_at_player),
+            ) = (
+                field_instance.identifier.as_ref(),
+                &field_instance.value,
+            ) {
+                turret.aim_at_player = *aim_at_player;
+            }
+            let value = match &field_instance.value {
+                FieldValue::Float(Some(v)) => *v,
+                FieldValue::Int(Some(v)) => *v as f32,
+                _ => continue,
+            };
+            match field_instance.identifier.as_ref() {
+                "interval" => turret.interval = value,
+                "angle" => turret.angle = value,
+                "range" => turret.range = value,
+                "speed" => turret.speed = value,
+                "gravity" => turret.gravity = value,
+                "lifetime" => turret.lifetime = value,
+                "damage" => {
+                    turret.damage = value.max(0.) as u32
+                }
+                "pierce" => {
+                    turret.pierce = value.max(0.) as u32
+                }
+                _ => {}
+            }
+        }
+
+        turret
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct TurretBundle {
+    #[sprite_sheet_bundle]
+    #[bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub turret: Turret,
 }
 
 #[derive(Clone, Default, Bundle, LdtkEntity)]
@@ -365,6 +652,63 @@ pub struct ChestBundle {
     pub collider_bundle: ColliderBundle,
 }
 
+/// An item lying in the level, waiting for a player
+/// to walk over it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+pub struct Pickup {
+    pub item: Item,
+}
+
+impl From<EntityInstance> for Pickup {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let item = entity_instance
+            .field_instances
+            .iter()
+            .find(|f| f.identifier == *"item")
+            .and_then(|field| match &field.value {
+                FieldValue::Enum(Some(item)) => {
+                    item.parse().ok()
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| {
+                warn!(
+                    "pickup {} has no valid `item` field",
+                    entity_instance.iid
+                );
+                Item::Potion
+            });
+        Pickup { item }
+    }
+}
+
+#[derive(Clone, Bundle, LdtkEntity)]
+pub struct PickupBundle {
+    #[sprite_sheet_bundle]
+    #[bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub pickup: Pickup,
+    #[from_entity_instance]
+    pub entity_instance: EntityInstance,
+}
+
+/// Touching one marks progress through the level
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+pub struct Checkpoint;
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct CheckpointBundle {
+    #[sprite_sheet_bundle]
+    #[bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    pub checkpoint: Checkpoint,
+    #[from_entity_instance]
+    pub entity_instance: EntityInstance,
+}
+
 #[derive(Clone, Default, Component, Resource)]
 pub struct GroundDetection {
     pub on_ground: bool,
@@ -375,3 +719,106 @@ pub struct GroundSensor {
     pub ground_detection_entity: Entity,
     pub intersecting_ground_entities: HashSet<Entity>,
 }
+
+/// Overrides how the camera behaves while the player
+/// is inside of the zone's rectangle.
+///
+/// Set up in LDtk as a `CameraZone` entity with
+/// these optional fields:
+/// - `lock_x`, `lock_y` (Bool): keep the camera
+///   centered on the zone along that axis. Locking
+///   both fixes the camera in place.
+/// - `zoom` (Float): scales the view, less than 1
+///   zooms in. Ignored in pixel-perfect mode, which
+///   always shows the same number of world pixels.
+/// - `bounded` (Bool): keep the camera inside the
+///   zone instead of the whole level
+/// - `priority` (Int): overlapping zones with a
+///   higher priority win
+/// - `blend` (Float): seconds to blend in and out
+#[derive(Clone, Debug, PartialEq, Component)]
+pub struct CameraZone {
+    pub size: Vec2,
+    pub lock_x: bool,
+    pub lock_y: bool,
+    pub zoom: f32,
+    pub bounded: bool,
+    pub priority: i32,
+    pub blend: f32,
+    /// How much the zone currently applies, from 0
+    /// to 1
+    pub weight: f32,
+}
+
+impl Default for CameraZone {
+    fn default() -> Self {
+        Self {
+            size: Vec2::ZERO,
+            lock_x: false,
+            lock_y: false,
+            zoom: 1.,
+            bounded: false,
+            priority: 0,
+            blend: 0.5,
+            weight: 0.,
+        }
+    }
+}
+
+impl From<EntityInstance> for CameraZone {
+    fn from(entity_instance: EntityInstance) -> Self {
+        let mut zone = CameraZone {
+            size: IVec2::new(
+                entity_instance.width,
+                entity_instance.height,
+            )
+            .as_vec2(),
+            ..default()
+        };
+
+        for field_instance in
+            &entity_instance.field_instances
+        {
+            match (
+                field_instance.identifier.as_ref(),
+                &field_instance.value,
+            ) {
+                ("lock_x", FieldValue::Bool(v)) => {
+                    zone.lock_x = *v
+                }
+                ("lock_y", FieldValue::Bool(v)) => {
+                    zone.lock_y = *v
+                }
+                ("zoom", FieldValue::Float(Some(v))) => {
+                    zone.zoom = *v
+                }
+                ("bounded", FieldValue::Bool(v)) => {
+                    zone.bounded = *v
+                }
+                ("priority", FieldValue::Int(Some(v))) => {
+                    zone.priority = *v
+                }
+                ("blend", FieldValue::Float(Some(v))) => {
+                    zone.blend = *v
+                }
+                _ => {}
+            }
+        }
+
+        zone
+    }
+}
+
+impl CameraZone {
+    /// The zone's world-space rectangle, given its
+    /// entity's (centered) translation
+    pub fn rect(&self, translation: Vec2) -> Rect {
+        Rect::from_center_size(translation, self.size)
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct CameraZoneBundle {
+    #[from_entity_instance]
+    pub camera_zone: CameraZone,
+}
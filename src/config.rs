@@ -0,0 +1,88 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use directories::ProjectDirs;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The platform's per-user config directory, e.g.
+/// `~/.config/platformer` on Linux
+pub fn config_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "platformer")
+        .map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+/// The platform's per-user data directory, e.g.
+/// `~/.local/share/platformer` on Linux, for things
+/// the game writes that aren't settings
+pub fn data_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "platformer")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+}
+
+/// Reads a json file from the [`config_dir`].
+///
+/// Returns `Ok(None)` if the file doesn't exist
+/// yet.
+pub fn load<T: DeserializeOwned>(
+    file_name: &str,
+) -> anyhow::Result<Option<T>> {
+    let path = config_dir()
+        .context("no config directory")?
+        .join(file_name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents =
+        fs::read_to_string(&path).with_context(|| {
+            format!("reading {}", path.display())
+        })?;
+    let value = serde_json::from_str(&contents)
+        .with_context(|| {
+            format!("parsing {}", path.display())
+        })?;
+    Ok(Some(value))
+}
+
+/// Writes a json file to the [`config_dir`]
+pub fn save<T: Serialize>(
+    file_name: &str,
+    value: &T,
+) -> anyhow::Result<()> {
+    let path = config_dir()
+        .context("no config directory")?
+        .join(file_name);
+    let contents = serde_json::to_string_pretty(value)?;
+    write_atomic(&path, contents.as_bytes())
+}
+
+/// Writes to a temporary file next to `path`, flushes
+/// it to disk and renames it over `path`, so a crash
+/// mid-write never leaves a truncated file behind
+pub fn write_atomic(
+    path: &Path,
+    contents: &[u8],
+) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| {
+            format!("creating {}", dir.display())
+        })?;
+    }
+    let tmp = path.with_extension("tmp");
+    let mut file =
+        File::create(&tmp).with_context(|| {
+            format!("creating {}", tmp.display())
+        })?;
+    file.write_all(contents)
+        .and_then(|()| file.sync_all())
+        .with_context(|| {
+            format!("writing {}", tmp.display())
+        })?;
+    fs::rename(&tmp, path).with_context(|| {
+        format!("replacing {}", path.display())
+    })?;
+    Ok(())
+}
@@ -0,0 +1,605 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use statig::{prelude::*, InitializedStatemachine};
+
+use crate::{
+    camera::gameplay_frozen,
+    combat::Knockback,
+    components::{EnemyStats, Patrol, Player},
+    movement::SimulationClock,
+    navigation::{LinkKind, Navigation, Waypoint},
+    GameState,
+};
+
+/// How long an enemy stands still after spotting a
+/// player, before giving chase
+const NOTICE_TIME: Duration = Duration::from_millis(400);
+/// How long an attack lasts
+const ATTACK_TIME: Duration = Duration::from_millis(500);
+/// How long a chasing enemy keeps going after losing
+/// sight of its target
+const GIVE_UP_TIME: Duration = Duration::from_secs(2);
+/// How close counts as back at the patrol route
+const HOME_DISTANCE: f32 = 2.;
+/// How often walking enemies look for a new path to
+/// where they're going
+const REPLAN_TIME: Duration = Duration::from_millis(500);
+/// How close counts as at a waypoint
+const WAYPOINT_DISTANCE: f32 = 4.;
+/// How fast walking enemies fall, in px/s²
+const GRAVITY: f32 = 900.;
+/// How far over the higher end of a jump link an
+/// enemy's arc goes, in world pixels
+const JUMP_CLEARANCE: f32 = 16.;
+
+/// Enemies that notice, chase and attack players
+/// they can see, and wander back to their patrol
+/// when they lose them
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            think
+                .run_in_state(GameState::Playing)
+                .run_if_not(gameplay_frozen)
+                .label(EnemySystem::Think),
+        )
+        .add_system(
+            plan_paths
+                .run_in_state(GameState::Playing)
+                .run_if_not(gameplay_frozen)
+                .label(EnemySystem::PlanPaths)
+                .after(EnemySystem::Think),
+        )
+        .add_system(
+            move_enemies
+                .run_in_state(GameState::Playing)
+                .run_if_not(gameplay_frozen)
+                .after(EnemySystem::PlanPaths),
+        );
+    }
+}
+
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+pub enum EnemySystem {
+    Think,
+    PlanPaths,
+}
+
+#[derive(Default)]
+pub struct EnemyStateMachine {
+    /// Simulated time, set before every dispatch
+    now: Duration,
+    /// When the current state was entered
+    entered: Duration,
+    /// Where the target was last seen
+    target: Option<Vec2>,
+    last_seen: Duration,
+}
+
+#[derive(Debug)]
+pub enum EnemyEvent {
+    SawPlayer,
+    LostPlayer,
+    InRange,
+    /// The current state has run its course
+    Timeout,
+    /// Back at the patrol route
+    Home,
+    Stun,
+}
+
+#[derive(Component)]
+pub struct EnemyBrain(
+    InitializedStatemachine<EnemyStateMachine>,
+);
+
+impl Default for EnemyBrain {
+    fn default() -> Self {
+        Self(
+            EnemyStateMachine::default()
+                .state_machine()
+                .init(),
+        )
+    }
+}
+
+impl EnemyBrain {
+    pub fn state(&self) -> &State {
+        self.0.state()
+    }
+
+    /// Knocks the enemy out for its
+    /// [`EnemyStats::stun_time`], starting over if
+    /// it's already stunned
+    pub fn stun(&mut self, now: Duration) {
+        self.handle(now, EnemyEvent::Stun);
+    }
+
+    fn handle(&mut self, now: Duration, event: EnemyEvent) {
+        self.0.now = now;
+        self.0.handle(&event);
+    }
+
+    /// How long the enemy has been in its current
+    /// state
+    fn time_in_state(&self) -> Duration {
+        self.0.now.saturating_sub(self.0.entered)
+    }
+}
+
+#[state_machine(
+    initial = "State::patrol()",
+    on_transition = "Self::on_transition",
+    state(derive(Debug, Clone, PartialEq))
+)]
+impl EnemyStateMachine {
+    fn on_transition(
+        &mut self,
+        source: &State,
+        target: &State,
+    ) {
+        debug!(
+            "enemy went from `{:?}` to `{:?}`",
+            source, target
+        );
+        self.entered = self.now;
+    }
+
+    #[state]
+    fn patrol(event: &EnemyEvent) -> Response<State> {
+        match event {
+            EnemyEvent::SawPlayer => {
+                Transition(State::notice())
+            }
+            EnemyEvent::Stun => {
+                Transition(State::stunned())
+            }
+            _ => Handled,
+        }
+    }
+
+    #[state]
+    fn notice(event: &EnemyEvent) -> Response<State> {
+        match event {
+            EnemyEvent::Timeout => {
+                Transition(State::chase())
+            }
+            EnemyEvent::LostPlayer => {
+                Transition(State::patrol())
+            }
+            EnemyEvent::Stun => {
+                Transition(State::stunned())
+            }
+            _ => Handled,
+        }
+    }
+
+    #[state]
+    fn chase(event: &EnemyEvent) -> Response<State> {
+        match event {
+            EnemyEvent::InRange => {
+                Transition(State::attack())
+            }
+            EnemyEvent::LostPlayer => {
+                Transition(State::returning())
+            }
+            EnemyEvent::Stun => {
+                Transition(State::stunned())
+            }
+            _ => Handled,
+        }
+    }
+
+    #[state]
+    fn attack(event: &EnemyEvent) -> Response<State> {
+        match event {
+            EnemyEvent::Timeout => {
+                Transition(State::chase())
+            }
+            EnemyEvent::Stun => {
+                Transition(State::stunned())
+            }
+            _ => Handled,
+        }
+    }
+
+    #[state]
+    fn returning(event: &EnemyEvent) -> Response<State> {
+        match event {
+            EnemyEvent::SawPlayer => {
+                Transition(State::notice())
+            }
+            EnemyEvent::Home => Transition(State::patrol()),
+            EnemyEvent::Stun => {
+                Transition(State::stunned())
+            }
+            _ => Handled,
+        }
+    }
+
+    #[state]
+    fn stunned(event: &EnemyEvent) -> Response<State> {
+        match event {
+            EnemyEvent::Timeout => {
+                Transition(State::returning())
+            }
+            // a fresh hit starts the stun over
+            EnemyEvent::Stun => {
+                Transition(State::stunned())
+            }
+            _ => Handled,
+        }
+    }
+}
+
+/// The way a walking enemy is taking to its target,
+/// or back home
+#[derive(Clone, Debug, Default, Component)]
+pub struct EnemyPath {
+    waypoints: VecDeque<Waypoint>,
+    planned: Option<Duration>,
+    /// Where the jump or drop in progress comes down
+    landing: Option<Vec2>,
+}
+
+impl EnemyPath {
+    /// The next waypoint, if there's a path
+    pub fn next(&self) -> Option<Vec2> {
+        self.waypoints
+            .front()
+            .map(|waypoint| waypoint.position)
+    }
+}
+
+/// Where an enemy heads back to when it gives up a
+/// chase
+fn home(patrol: &Patrol) -> Option<Vec2> {
+    patrol
+        .points
+        .get(patrol.index)
+        .or_else(|| patrol.points.first())
+        .copied()
+}
+
+/// Whether nothing solid is between `from` and `to`.
+/// Only fixed colliders, like the merged walls,
+/// block sight.
+pub fn line_of_sight(
+    rapier_context: &RapierContext,
+    from: Vec2,
+    to: Vec2,
+) -> bool {
+    let offset = to - from;
+    let distance = offset.length();
+    if distance <= f32::EPSILON {
+        return true;
+    }
+    rapier_context
+        .cast_ray(
+            from,
+            offset / distance,
+            distance,
+            true,
+            QueryFilter::only_fixed().exclude_sensors(),
+        )
+        .is_none()
+}
+
+/// Feeds each enemy's state machine what it can see
+/// and how long it's been at what it's doing
+fn think(
+    clock: Res<SimulationClock>,
+    rapier_context: Res<RapierContext>,
+    players: Query<&GlobalTransform, With<Player>>,
+    mut enemies: Query<(
+        &GlobalTransform,
+        &EnemyStats,
+        &Patrol,
+        &mut EnemyBrain,
+    )>,
+) {
+    let now = clock.elapsed();
+    for (transform, stats, patrol, mut brain) in
+        enemies.iter_mut()
+    {
+        let brain = &mut *brain;
+        brain.0.now = now;
+        let position = transform.translation().truncate();
+
+        let seen = players
+            .iter()
+            .map(|player| player.translation().truncate())
+            .filter(|player| {
+                player.distance(position)
+                    <= stats.aggro_range
+                    && line_of_sight(
+                        &rapier_context,
+                        position,
+                        *player,
+                    )
+            })
+            .min_by(|a, b| {
+                a.distance(position)
+                    .total_cmp(&b.distance(position))
+            });
+        if let Some(player) = seen {
+            brain.0.target = Some(player);
+            brain.0.last_seen = now;
+        }
+
+        let event = match brain.state() {
+            State::Patrol {} | State::Returning {}
+                if seen.is_some() =>
+            {
+                EnemyEvent::SawPlayer
+            }
+            State::Returning {} => match home(patrol) {
+                Some(home)
+                    if home.distance(position)
+                        > HOME_DISTANCE =>
+                {
+                    continue
+                }
+                _ => EnemyEvent::Home,
+            },
+            State::Notice {} if seen.is_none() => {
+                EnemyEvent::LostPlayer
+            }
+            State::Notice {}
+                if brain.time_in_state() >= NOTICE_TIME =>
+            {
+                EnemyEvent::Timeout
+            }
+            State::Chase {}
+                if seen.is_some_and(|player| {
+                    player.distance(position)
+                        <= stats.attack_range
+                }) =>
+            {
+                EnemyEvent::InRange
+            }
+            State::Chase {}
+                if now
+                    .saturating_sub(brain.0.last_seen)
+                    >= GIVE_UP_TIME =>
+            {
+                EnemyEvent::LostPlayer
+            }
+            State::Attack {}
+                if brain.time_in_state() >= ATTACK_TIME =>
+            {
+                EnemyEvent::Timeout
+            }
+            State::Stunned {}
+                if brain.time_in_state()
+                    >= Duration::from_secs_f32(
+                        stats.stun_time,
+                    ) =>
+            {
+                EnemyEvent::Timeout
+            }
+            _ => continue,
+        };
+        brain.handle(now, event);
+    }
+}
+
+/// Keeps walking enemies' paths to whatever they're
+/// after up to date
+fn plan_paths(
+    clock: Res<SimulationClock>,
+    navigation: Navigation,
+    mut enemies: Query<(
+        &GlobalTransform,
+        &EnemyStats,
+        &Patrol,
+        &EnemyBrain,
+        &mut EnemyPath,
+    )>,
+) {
+    let now = clock.elapsed();
+    for (transform, stats, patrol, brain, mut path) in
+        enemies.iter_mut()
+    {
+        let goal = match brain.state() {
+            State::Chase {} => brain.0.target,
+            State::Returning {} => home(patrol),
+            _ => None,
+        };
+        let goal = match goal {
+            Some(goal) if stats.walks => goal,
+            _ => {
+                path.waypoints.clear();
+                path.planned = None;
+                continue;
+            }
+        };
+        // the path can wait until it's back on the
+        // ground
+        if path.landing.is_some() {
+            continue;
+        }
+        let position = transform.translation().truncate();
+        let path = &mut *path;
+
+        while path.next().is_some_and(|next| {
+            next.distance(position) <= WAYPOINT_DISTANCE
+        }) {
+            path.waypoints.pop_front();
+        }
+        if path.planned.is_none_or(|planned| {
+            now.saturating_sub(planned) >= REPLAN_TIME
+        }) {
+            path.waypoints = navigation
+                .find_path(
+                    position,
+                    goal,
+                    stats.jump_height,
+                )
+                .unwrap_or_default()
+                .into();
+            path.planned = Some(now);
+        }
+    }
+}
+
+/// Steers every enemy that isn't patrolling. The
+/// patrol itself is walked by
+/// [`crate::systems::patrol`].
+fn move_enemies(
+    time: Res<Time>,
+    clock: Res<SimulationClock>,
+    mut enemies: Query<(
+        &GlobalTransform,
+        &EnemyStats,
+        &Patrol,
+        &EnemyBrain,
+        &mut EnemyPath,
+        Option<&Knockback>,
+        &mut Velocity,
+    )>,
+) {
+    let dt = time.delta_seconds();
+    for (
+        transform,
+        stats,
+        patrol,
+        brain,
+        mut path,
+        knockback,
+        mut velocity,
+    ) in enemies.iter_mut()
+    {
+        let position = transform.translation().truncate();
+        // mid-air, whatever the brain says
+        if let Some(landing) = path.landing {
+            velocity.linvel = fly(
+                &mut path,
+                landing,
+                position,
+                velocity.linvel,
+                dt,
+            );
+            continue;
+        }
+        let towards = |target: Option<Vec2>, speed: f32| {
+            target.map_or(Vec2::ZERO, |target| {
+                (target - position).normalize_or_zero()
+                    * speed
+            })
+        };
+        // walking enemies can't follow a player up
+        // into the air
+        let at_player = |speed: f32| {
+            towards(
+                brain.0.target.map(|target| {
+                    if stats.walks {
+                        Vec2::new(target.x, position.y)
+                    } else {
+                        target
+                    }
+                }),
+                speed,
+            )
+        };
+        velocity.linvel = match brain.state() {
+            State::Patrol {} => continue,
+            State::Notice {} => Vec2::ZERO,
+            // hit enemies reel from the blow
+            State::Stunned {} => knockback
+                .and_then(|knockback| {
+                    knockback.velocity_at(clock.elapsed())
+                })
+                .unwrap_or_default(),
+            State::Chase {} => follow_path(
+                &mut path,
+                position,
+                stats.chase_speed,
+            )
+            .unwrap_or_else(|| {
+                at_player(stats.chase_speed)
+            }),
+            // lunge at where the target was
+            State::Attack {} => {
+                at_player(stats.chase_speed * 2.)
+            }
+            State::Returning {} => follow_path(
+                &mut path,
+                position,
+                stats.speed,
+            )
+            .unwrap_or_else(|| {
+                towards(home(patrol), stats.speed)
+            }),
+        };
+    }
+}
+
+/// Heads for the next waypoint the way the link there
+/// needs: walking, stepping off the ledge and
+/// falling, or jumping
+fn follow_path(
+    path: &mut EnemyPath,
+    position: Vec2,
+    speed: f32,
+) -> Option<Vec2> {
+    let waypoint = *path.waypoints.front()?;
+    let offset = waypoint.position - position;
+    Some(match waypoint.kind {
+        LinkKind::Walk => {
+            offset.normalize_or_zero() * speed
+        }
+        LinkKind::Drop
+            if offset.x.abs() > WAYPOINT_DISTANCE =>
+        {
+            Vec2::new(offset.x.signum() * speed, 0.)
+        }
+        LinkKind::Drop => {
+            path.landing = Some(waypoint.position);
+            Vec2::ZERO
+        }
+        LinkKind::Jump => {
+            path.landing = Some(waypoint.position);
+            jump_velocity(offset)
+        }
+    })
+}
+
+/// The take-off velocity for an arc that clears
+/// [`JUMP_CLEARANCE`] over the higher end and comes
+/// down `offset` away
+fn jump_velocity(offset: Vec2) -> Vec2 {
+    let rise = offset.y.max(0.) + JUMP_CLEARANCE;
+    let up = (2. * GRAVITY * rise).sqrt();
+    let airtime = up / GRAVITY
+        + (2. * (rise - offset.y) / GRAVITY).sqrt();
+    Vec2::new(offset.x / airtime, up)
+}
+
+/// Carries a jump or drop on under gravity, touching
+/// down on `landing` rather than falling through it
+fn fly(
+    path: &mut EnemyPath,
+    landing: Vec2,
+    position: Vec2,
+    linvel: Vec2,
+    dt: f32,
+) -> Vec2 {
+    let linvel = linvel - Vec2::Y * GRAVITY * dt;
+    if dt > 0.
+        && linvel.y < 0.
+        && position.y + linvel.y * dt <= landing.y
+    {
+        path.landing = None;
+        path.waypoints.pop_front();
+        return (landing - position) / dt;
+    }
+    linvel
+}
@@ -1,26 +1,184 @@
-use crate::actions::*;
+use crate::{
+    actions::*,
+    bindings::{InputProfiles, InputSource},
+    components::{Player, PlayerBundle, PlayerSlot},
+    GameState,
+};
 use bevy::prelude::*;
-use leafwing_input_manager::prelude::InputMap;
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::{
+    prelude::InputMap,
+    user_input::{InputKind, UserInput},
+};
+
+pub const MAX_PLAYERS: usize = 4;
 
 pub struct GamepadPlugin;
 
 impl Plugin for GamepadPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(gamepad_connections)
+        app.init_resource::<PlayerSlots>()
+            .add_system(gamepad_connections)
             // .add_system(gamepad_input)
-            .add_system(on_change_gamepad)
-            .add_system(animate_sprite);
+            .add_system(
+                join_players.run_not_in_state(
+                    GameState::AssetLoading,
+                ),
+            )
+            .add_system(assign_level_player)
+            .add_system(
+                spawn_joined_players
+                    .after(assign_level_player),
+            )
+            .add_system(apply_slot_bindings);
     }
 }
-/// Simple resource to store the ID of the
-/// connected gamepad. We need to know which
-/// gamepad to use for player input.
-#[derive(Debug, Resource)]
-pub struct MyGamepad(pub Gamepad);
 
+/// A joined player and the input they play with
+#[derive(Clone, Copy, Debug)]
+pub struct Slot {
+    pub source: InputSource,
+    /// `false` while the slot's gamepad is
+    /// unplugged
+    pub connected: bool,
+}
+
+/// Everyone who pressed Start to join, in the order
+/// they joined. A [`PlayerSlot`] indexes into this.
+#[derive(Debug, Default, Resource)]
+pub struct PlayerSlots(pub Vec<Slot>);
+
+impl PlayerSlots {
+    /// Adds `source` as a new player, or hands it a
+    /// slot whose gamepad was unplugged. Returns the
+    /// slot's index, or `None` if `source` already
+    /// has one or everyone's slot is taken.
+    pub fn join(
+        &mut self,
+        source: InputSource,
+    ) -> Option<usize> {
+        if self.slot_of(source).is_some() {
+            return None;
+        }
+        if let InputSource::Gamepad(_) = source {
+            if let Some(index) =
+                self.0.iter().position(|slot| {
+                    !slot.connected
+                        && matches!(
+                            slot.source,
+                            InputSource::Gamepad(_)
+                        )
+                })
+            {
+                self.0[index] = Slot {
+                    source,
+                    connected: true,
+                };
+                return Some(index);
+            }
+        }
+        if self.0.len() < MAX_PLAYERS {
+            self.0.push(Slot {
+                source,
+                connected: true,
+            });
+            Some(self.0.len() - 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn slot_of(
+        &self,
+        source: InputSource,
+    ) -> Option<usize> {
+        self.0.iter().position(|slot| slot.source == source)
+    }
+}
+
+/// Whether `source` just pressed one of the inputs
+/// bound to `action`, checked straight from the
+/// devices since it may not belong to a player yet
+pub fn source_just_pressed(
+    source: InputSource,
+    action: PlatformerAction,
+    profiles: &InputProfiles,
+    keys: &Input<KeyCode>,
+    buttons: &Input<GamepadButton>,
+) -> bool {
+    profiles.input_map_for(source).get(action).iter().any(
+        |input| match (input, source) {
+            (
+                UserInput::Single(InputKind::Keyboard(key)),
+                _,
+            ) => keys.just_pressed(*key),
+            (
+                UserInput::Single(
+                    InputKind::GamepadButton(button_type),
+                ),
+                InputSource::Gamepad(gamepad),
+            ) => buttons.just_pressed(GamepadButton::new(
+                gamepad,
+                *button_type,
+            )),
+            _ => false,
+        },
+    )
+}
+
+/// Every keyboard half and connected gamepad
+pub fn input_sources(
+    gamepads: &Gamepads,
+) -> impl Iterator<Item = InputSource> + '_ {
+    [InputSource::KeyboardLeft, InputSource::KeyboardRight]
+        .into_iter()
+        .chain(gamepads.iter().map(InputSource::Gamepad))
+}
+
+/// Pressing Start (the `Pause` binding) on a
+/// keyboard half or gamepad that isn't playing yet
+/// joins the game.
+///
+/// Mid-game this only works once someone has joined:
+/// without slots the level's player listens to every
+/// device, and Start is their pause button.
+fn join_players(
+    state: Res<CurrentState<GameState>>,
+    mut slots: ResMut<PlayerSlots>,
+    profiles: Res<InputProfiles>,
+    gamepads: Res<Gamepads>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+) {
+    if state.0 != GameState::Joining && slots.0.is_empty() {
+        return;
+    }
+    for source in input_sources(&gamepads) {
+        if slots.slot_of(source).is_none()
+            && source_just_pressed(
+                source,
+                PlatformerAction::Pause,
+                &profiles,
+                &keys,
+                &buttons,
+            )
+        {
+            if let Some(index) = slots.join(source) {
+                info!(
+                    "{source:?} joined as player {}",
+                    index + 1
+                );
+            }
+        }
+    }
+}
+
+/// Keeps track of unplugged gamepads so that a pad
+/// coming back gets its old slot, instead of
+/// whichever player happens to be first
 fn gamepad_connections(
-    mut commands: Commands,
-    my_gamepad: Option<Res<MyGamepad>>,
+    mut slots: ResMut<PlayerSlots>,
     mut gamepad_evr: EventReader<GamepadEvent>,
 ) {
     for GamepadEvent {
@@ -29,37 +187,28 @@ fn gamepad_connections(
     } in gamepad_evr.iter()
     {
         match event_type {
-            GamepadEventType::Connected(_info) => {
-                // dbg!(info);
-                println!(
-                    "New gamepad connected with ID: {:?}",
-                    gamepad.id
-                );
+            GamepadEventType::Connected(_) => {
+                info!("gamepad {} connected", gamepad.id);
 
-                // if we don't have any gamepad yet, use
-                // this one
-                if my_gamepad.is_none() {
-                    commands.insert_resource(MyGamepad(
-                        gamepad.clone(),
-                    ));
+                // the same pad plugged back in, a
+                // different one has to press Start
+                // to take over the slot
+                if let Some(index) = slots
+                    .slot_of(InputSource::Gamepad(*gamepad))
+                {
+                    slots.0[index].connected = true;
                 }
             }
             GamepadEventType::Disconnected => {
-                println!(
-                    "Lost gamepad connection with ID: {:?}",
+                info!(
+                    "gamepad {} disconnected",
                     gamepad.id
                 );
 
-                // if it's the one we previously associated
-                // with the player,
-                // disassociate it:
-                if let Some(MyGamepad(old_id)) =
-                    my_gamepad.as_deref()
+                if let Some(index) = slots
+                    .slot_of(InputSource::Gamepad(*gamepad))
                 {
-                    if old_id == gamepad {
-                        commands
-                            .remove_resource::<MyGamepad>();
-                    }
+                    slots.0[index].connected = false;
                 }
             }
             // other events are irrelevant
@@ -68,16 +217,100 @@ fn gamepad_connections(
     }
 }
 
-fn on_change_gamepad(
-    gamepad: Option<Res<MyGamepad>>,
-    mut input_map: Query<&mut InputMap<PlatformerAction>>,
+/// The player placed in the LDtk level is always
+/// the first slot
+fn assign_level_player(
+    mut commands: Commands,
+    players: Query<
+        Entity,
+        (Added<Player>, Without<PlayerSlot>),
+    >,
+) {
+    for player in players.iter() {
+        commands.entity(player).insert(PlayerSlot(0));
+    }
+}
+
+/// Spawns a player next to the first one for every
+/// other joined slot
+fn spawn_joined_players(
+    mut commands: Commands,
+    slots: Res<PlayerSlots>,
+    players: Query<(
+        &PlayerSlot,
+        &EntityInstance,
+        &Transform,
+        Option<&Parent>,
+    )>,
 ) {
-    if let Some(gamepad) = gamepad {
-        if gamepad.is_changed() {
-            for mut map in input_map.iter_mut() {
-                map.set_gamepad(gamepad.0);
+    let (instance, transform, parent) =
+        match players.iter().find(|(slot, ..)| slot.0 == 0)
+        {
+            Some((_, instance, transform, parent)) => {
+                (instance, transform, parent)
             }
+            None => return,
+        };
+
+    for index in 1..slots.0.len() {
+        if players.iter().any(|(slot, ..)| slot.0 == index)
+        {
+            continue;
+        }
+        let mut bundle =
+            PlayerBundle::from_entity_instance(instance);
+        // LDtk only knows about the first player
+        bundle.worldly.entity_iid =
+            format!("{}-{}", instance.iid, index);
+        let player = commands
+            .spawn((
+                bundle,
+                SpatialBundle::from_transform(*transform),
+                PlayerSlot(index),
+            ))
+            .id();
+        if let Some(parent) = parent {
+            commands.entity(parent.get()).add_child(player);
+        }
+    }
+}
+
+/// Gives each player the bindings of their slot's
+/// keyboard half or gamepad. Players without a slot,
+/// when nobody joined, take input from everything.
+fn apply_slot_bindings(
+    slots: Res<PlayerSlots>,
+    profiles: Res<InputProfiles>,
+    mut players: Query<(
+        &PlayerSlot,
+        &mut InputMap<PlatformerAction>,
+        ChangeTrackers<PlayerSlot>,
+    )>,
+) {
+    for (slot, mut input_map, tracker) in players.iter_mut()
+    {
+        if !(slots.is_changed()
+            || profiles.is_changed()
+            || tracker.is_changed())
+        {
+            continue;
         }
+        *input_map =
+            slot_input_map(&slots, &profiles, *slot);
+    }
+}
+
+/// The bindings for whoever plays in `slot`
+pub fn slot_input_map(
+    slots: &PlayerSlots,
+    profiles: &InputProfiles,
+    slot: PlayerSlot,
+) -> InputMap<PlatformerAction> {
+    match slots.0.get(slot.0) {
+        Some(joined) => {
+            profiles.input_map_for(joined.source)
+        }
+        None => profiles.input_map(),
     }
 }
 // fn gamepad_input(
@@ -206,25 +439,3 @@ fn on_change_gamepad(
 //         }
 //     }
 // }
-
-#[derive(Component, Deref, DerefMut)]
-pub struct AnimationTimer(pub Timer);
-
-fn animate_sprite(
-    time: Res<Time>,
-    mut query: Query<(
-        &mut AnimationTimer,
-        &mut TextureAtlasSprite,
-    )>,
-) {
-    for (mut timer, mut sprite) in query.iter_mut() {
-        timer.tick(time.delta());
-        if timer.just_finished() {
-            sprite.index = match sprite.index {
-                9 => 10,
-                10 => 9,
-                _ => 9,
-            }
-        }
-    }
-}
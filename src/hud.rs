@@ -0,0 +1,410 @@
+use bevy::prelude::*;
+
+use crate::{
+    combat::{DamageTaken, Healed},
+    components::{HealMeter, Health, Player},
+    movement::{Dash, SimulationClock},
+};
+
+/// The window height the HUD is laid out for. Every
+/// UI node, menus included, is scaled from it.
+const REFERENCE_HEIGHT: f32 = 720.;
+const PIP_SIZE: f32 = 20.;
+const METER_SIZE: Vec2 = Vec2::new(90., 10.);
+/// How long a pip swells for when it's lost or
+/// regained, in seconds
+const PULSE_TIME: f32 = 0.3;
+/// How much bigger a pip gets at the height of its
+/// pulse
+const PULSE_GROWTH: f32 = 0.5;
+/// How long the screen flashes red when a player is
+/// hurt, in seconds
+const FLASH_TIME: f32 = 0.3;
+const FLASH_ALPHA: f32 = 0.35;
+
+const PIP_FULL: Color = Color::rgb(0.85, 0.15, 0.2);
+const PIP_EMPTY: Color = Color::rgba(0.2, 0.2, 0.2, 0.8);
+const SLOT_BACKGROUND: Color =
+    Color::rgba(0.1, 0.1, 0.1, 0.8);
+const METER_CHARGING: Color = Color::rgb(0.3, 0.5, 0.8);
+/// Enough charge to heal
+const METER_READY: Color = Color::rgb(0.5, 0.85, 1.);
+const DASH_COOLING: Color = Color::rgb(0.5, 0.5, 0.5);
+const DASH_READY: Color = Color::rgb(1., 0.8, 0.2);
+
+/// Each player's health, heal meter and dash,
+/// drawn over the game
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_hud)
+            .add_system(scale_ui)
+            .add_system(add_player_huds)
+            .add_system(remove_player_huds)
+            .add_system(pulse_pips)
+            .add_system(draw_health_pips.after(pulse_pips))
+            .add_system(draw_heal_meters)
+            .add_system(draw_dash_indicators)
+            .add_system(flash_on_damage);
+    }
+}
+
+/// Holds a row for each player
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct HudRoot;
+
+/// A player's row of the HUD
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+struct PlayerHud {
+    player: Entity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+struct HealthPip {
+    player: Entity,
+    index: u32,
+}
+
+/// Swells a [`HealthPip`], starting at `started`
+/// seconds of `Time`
+#[derive(Clone, Copy, Debug, Component)]
+struct Pulse {
+    started: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+struct HealMeterFill {
+    player: Entity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+struct DashFill {
+    player: Entity,
+}
+
+/// The red wash over the whole screen when a player
+/// is hurt
+#[derive(Clone, Copy, Debug, Default, Component)]
+struct DamageFlash {
+    started: Option<f32>,
+}
+
+fn spawn_hud(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(16.),
+                    top: Val::Px(16.),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+        HudRoot,
+    ));
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                size: Size::new(
+                    Val::Percent(100.),
+                    Val::Percent(100.),
+                ),
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            background_color: Color::NONE.into(),
+            z_index: ZIndex::Global(9),
+            ..default()
+        },
+        DamageFlash::default(),
+    ));
+}
+
+/// Keeps the UI the same size relative to the
+/// window
+fn scale_ui(
+    windows: Res<Windows>,
+    mut ui_scale: ResMut<UiScale>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let scale = (window.height() / REFERENCE_HEIGHT)
+        .max(0.25) as f64;
+    if ui_scale.scale != scale {
+        ui_scale.scale = scale;
+    }
+}
+
+/// A slot in the HUD, with a fill that grows along
+/// `direction`
+fn slot(
+    size: Vec2,
+    direction: FlexDirection,
+) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(
+                Val::Px(size.x),
+                Val::Px(size.y),
+            ),
+            margin: UiRect::left(Val::Px(12.)),
+            flex_direction: direction,
+            ..default()
+        },
+        background_color: SLOT_BACKGROUND.into(),
+        ..default()
+    }
+}
+
+fn add_player_huds(
+    mut commands: Commands,
+    roots: Query<Entity, With<HudRoot>>,
+    players: Query<(Entity, &Health), Added<Player>>,
+) {
+    let root = match roots.get_single() {
+        Ok(root) => root,
+        Err(_) => return,
+    };
+    for (player, health) in players.iter() {
+        let row = commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        margin: UiRect::bottom(Val::Px(8.)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                PlayerHud { player },
+            ))
+            .with_children(|row| {
+                for index in 0..health.max {
+                    row.spawn((
+                        NodeBundle {
+                            style: Style {
+                                size: Size::new(
+                                    Val::Px(PIP_SIZE),
+                                    Val::Px(PIP_SIZE),
+                                ),
+                                margin: UiRect::right(
+                                    Val::Px(4.),
+                                ),
+                                ..default()
+                            },
+                            background_color: PIP_FULL
+                                .into(),
+                            ..default()
+                        },
+                        HealthPip { player, index },
+                    ));
+                }
+                row.spawn(slot(
+                    METER_SIZE,
+                    FlexDirection::Row,
+                ))
+                .with_children(|meter| {
+                    meter.spawn((
+                        NodeBundle::default(),
+                        HealMeterFill { player },
+                    ));
+                });
+                // fills from the bottom up
+                row.spawn(slot(
+                    Vec2::splat(PIP_SIZE),
+                    FlexDirection::ColumnReverse,
+                ))
+                .with_children(|dash| {
+                    dash.spawn((
+                        NodeBundle::default(),
+                        DashFill { player },
+                    ));
+                });
+            })
+            .id();
+        commands.entity(root).add_child(row);
+    }
+}
+
+fn remove_player_huds(
+    mut commands: Commands,
+    huds: Query<(Entity, &PlayerHud)>,
+    players: Query<(), With<Player>>,
+) {
+    for (entity, hud) in huds.iter() {
+        if !players.contains(hud.player) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Swells the pips a player just lost or regained
+fn pulse_pips(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut taken: EventReader<DamageTaken>,
+    mut healed: EventReader<Healed>,
+    pips: Query<(Entity, &HealthPip)>,
+) {
+    let changed = taken
+        .iter()
+        .map(|event| {
+            let lost = event.remaining + event.amount;
+            (event.target, event.remaining..lost)
+        })
+        .chain(healed.iter().map(|event| {
+            let before = event.current - event.amount;
+            (event.target, before..event.current)
+        }))
+        .collect::<Vec<_>>();
+    for (player, indices) in changed {
+        for (entity, pip) in pips.iter() {
+            if pip.player == player
+                && indices.contains(&pip.index)
+            {
+                commands.entity(entity).insert(Pulse {
+                    started: time.elapsed_seconds(),
+                });
+            }
+        }
+    }
+}
+
+fn draw_health_pips(
+    mut commands: Commands,
+    time: Res<Time>,
+    healths: Query<&Health>,
+    mut pips: Query<(
+        Entity,
+        &HealthPip,
+        Option<&Pulse>,
+        &mut Style,
+        &mut BackgroundColor,
+    )>,
+) {
+    for (entity, pip, pulse, mut style, mut color) in
+        pips.iter_mut()
+    {
+        let health = match healths.get(pip.player) {
+            Ok(health) => health,
+            Err(_) => continue,
+        };
+        let full = pip.index < health.current;
+
+        let t = pulse.map_or(1., |pulse| {
+            (time.elapsed_seconds() - pulse.started)
+                / PULSE_TIME
+        });
+        if t >= 1. && pulse.is_some() {
+            commands.entity(entity).remove::<Pulse>();
+        }
+        let t = t.clamp(0., 1.);
+        let size = PIP_SIZE
+            * (1.
+                + PULSE_GROWTH
+                    * (t * std::f32::consts::PI).sin());
+        style.size =
+            Size::new(Val::Px(size), Val::Px(size));
+        // flashes white for the first half of a pulse
+        color.0 = if t < 0.5 {
+            Color::WHITE
+        } else if full {
+            PIP_FULL
+        } else {
+            PIP_EMPTY
+        };
+    }
+}
+
+fn draw_heal_meters(
+    meters: Query<&HealMeter>,
+    mut fills: Query<(
+        &HealMeterFill,
+        &mut Style,
+        &mut BackgroundColor,
+    )>,
+) {
+    for (fill, mut style, mut color) in fills.iter_mut() {
+        let meter = match meters.get(fill.player) {
+            Ok(meter) => meter,
+            Err(_) => continue,
+        };
+        let fraction =
+            meter.charge as f32 / meter.max.max(1) as f32;
+        style.size = Size::new(
+            Val::Percent(fraction * 100.),
+            Val::Percent(100.),
+        );
+        color.0 = if meter.charge >= meter.cost {
+            METER_READY
+        } else {
+            METER_CHARGING
+        };
+    }
+}
+
+fn draw_dash_indicators(
+    clock: Res<SimulationClock>,
+    dashes: Query<&Dash>,
+    mut fills: Query<(
+        &DashFill,
+        &mut Style,
+        &mut BackgroundColor,
+    )>,
+) {
+    for (fill, mut style, mut color) in fills.iter_mut() {
+        let dash = match dashes.get(fill.player) {
+            Ok(dash) => dash,
+            Err(_) => continue,
+        };
+        let readiness =
+            dash.readiness(clock.elapsed()).clamp(0., 1.);
+        style.size = Size::new(
+            Val::Percent(100.),
+            Val::Percent(readiness * 100.),
+        );
+        color.0 = if readiness >= 1. {
+            DASH_READY
+        } else {
+            DASH_COOLING
+        };
+    }
+}
+
+fn flash_on_damage(
+    time: Res<Time>,
+    mut taken: EventReader<DamageTaken>,
+    players: Query<(), With<Player>>,
+    mut flashes: Query<(
+        &mut DamageFlash,
+        &mut BackgroundColor,
+    )>,
+) {
+    let hurt = taken
+        .iter()
+        .any(|event| players.contains(event.target));
+    let now = time.elapsed_seconds();
+    for (mut flash, mut color) in flashes.iter_mut() {
+        if hurt {
+            flash.started = Some(now);
+        }
+        let alpha = match flash.started {
+            Some(started) => {
+                let t = (now - started) / FLASH_TIME;
+                FLASH_ALPHA * (1. - t).max(0.)
+            }
+            None => 0.,
+        };
+        color.0 = Color::rgba(1., 0., 0., alpha);
+    }
+}
@@ -0,0 +1,532 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+};
+
+use bevy::{math::Rect, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::PlatformerAction,
+    combat::Healed,
+    components::{
+        Equipment, Health, Items, Pickup, Player,
+    },
+    menu::{
+        spawn_menu, Menu, MenuBack, MenuBuilder,
+        MenuConfirmed, MenuItem, MenuSystem,
+    },
+    pause::PauseScreen,
+    GameState,
+};
+
+pub struct ItemsPlugin;
+
+impl Plugin for ItemsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ItemRegistry>()
+            .init_resource::<CollectedPickups>()
+            .add_system(
+                remove_collected_pickups
+                    .label(ItemSystem::RemoveCollected),
+            )
+            .add_system(
+                collect_pickups
+                    .run_in_state(GameState::Playing)
+                    .after(ItemSystem::RemoveCollected),
+            )
+            .add_system(
+                open_inventory
+                    .run_in_state(GameState::Playing),
+            )
+            .add_enter_system(
+                GameState::Paused,
+                spawn_inventory,
+            )
+            .add_exit_system(
+                GameState::Paused,
+                despawn_inventory,
+            )
+            .add_system(
+                inventory_actions
+                    .run_in_state(GameState::Paused)
+                    .label(ItemSystem::Inventory)
+                    .after(MenuSystem::Navigate),
+            )
+            .add_system(
+                describe_selected_item
+                    .run_in_state(GameState::Paused)
+                    .after(ItemSystem::Inventory),
+            );
+    }
+}
+
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+pub enum ItemSystem {
+    RemoveCollected,
+    Inventory,
+}
+
+/// Every item in the game. LDtk's `Item` enum
+/// uses the same identifiers.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+pub enum Item {
+    Knife,
+    Boot,
+    Potion,
+    Key,
+}
+
+impl FromStr for Item {
+    type Err = String;
+
+    fn from_str(
+        identifier: &str,
+    ) -> Result<Self, Self::Err> {
+        match identifier {
+            "Knife" => Ok(Item::Knife),
+            "Boot" => Ok(Item::Boot),
+            "Potion" => Ok(Item::Potion),
+            "Key" => Ok(Item::Key),
+            _ => {
+                Err(format!("unknown item `{identifier}`"))
+            }
+        }
+    }
+}
+
+/// Where an equipped item goes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EquipSlot {
+    Weapon,
+    Feet,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ItemKind {
+    Equipment(EquipSlot),
+    /// Used up when consumed
+    Consumable(Effect),
+    /// Can't be used from the inventory
+    Quest,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    Heal(u32),
+}
+
+#[derive(Clone, Debug)]
+pub struct ItemDef {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: ItemKind,
+}
+
+/// What every [`Item`] is and does
+#[derive(Debug, Resource)]
+pub struct ItemRegistry(HashMap<Item, ItemDef>);
+
+impl Default for ItemRegistry {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (
+                Item::Knife,
+                ItemDef {
+                    name: "Knife",
+                    description: "Short, but sharp",
+                    kind: ItemKind::Equipment(
+                        EquipSlot::Weapon,
+                    ),
+                },
+            ),
+            (
+                Item::Boot,
+                ItemDef {
+                    name: "Boot",
+                    description: "Only the one, sadly",
+                    kind: ItemKind::Equipment(
+                        EquipSlot::Feet,
+                    ),
+                },
+            ),
+            (
+                Item::Potion,
+                ItemDef {
+                    name: "Potion",
+                    description: "Restores 2 health",
+                    kind: ItemKind::Consumable(
+                        Effect::Heal(2),
+                    ),
+                },
+            ),
+            (
+                Item::Key,
+                ItemDef {
+                    name: "Key",
+                    description: "It must open something",
+                    kind: ItemKind::Quest,
+                },
+            ),
+        ]))
+    }
+}
+
+impl ItemRegistry {
+    pub fn get(&self, item: Item) -> &ItemDef {
+        self.0
+            .get(&item)
+            .expect("every item is in the registry")
+    }
+}
+
+/// The LDtk iids of pickups already taken, so they
+/// stay gone when their level is respawned or
+/// reloaded from a save
+#[derive(
+    Clone, Debug, Default, Resource, Serialize, Deserialize,
+)]
+pub struct CollectedPickups(pub HashSet<String>);
+
+fn remove_collected_pickups(
+    mut commands: Commands,
+    collected: Res<CollectedPickups>,
+    pickups: Query<
+        (Entity, &EntityInstance),
+        Added<Pickup>,
+    >,
+) {
+    for (entity, instance) in pickups.iter() {
+        if collected.0.contains(&instance.iid) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Touching a pickup adds its item to the player's
+/// [`Items`]
+fn collect_pickups(
+    mut commands: Commands,
+    mut collected: ResMut<CollectedPickups>,
+    mut players: Query<
+        (&GlobalTransform, &mut Items),
+        With<Player>,
+    >,
+    pickups: Query<(
+        Entity,
+        &Pickup,
+        &EntityInstance,
+        &GlobalTransform,
+    )>,
+) {
+    // roughly the player's capsule
+    let reach = Vec2::new(12., 24.);
+    for (entity, pickup, instance, transform) in
+        pickups.iter()
+    {
+        if collected.0.contains(&instance.iid) {
+            continue;
+        }
+        let area = Rect::from_center_size(
+            transform.translation().truncate(),
+            IVec2::new(instance.width, instance.height)
+                .as_vec2()
+                + reach * 2.,
+        );
+        if let Some((_, mut items)) =
+            players.iter_mut().find(|(player, _)| {
+                area.contains(
+                    player.translation().truncate(),
+                )
+            })
+        {
+            items.0.push(pickup.item);
+            collected.0.insert(instance.iid.clone());
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// `Menus` pauses the game and shows the inventory
+/// of the player who pressed it
+fn open_inventory(
+    mut commands: Commands,
+    players: Query<
+        (Entity, &ActionState<PlatformerAction>),
+        With<Player>,
+    >,
+) {
+    if let Some((player, _)) =
+        players.iter().find(|(_, action_state)| {
+            action_state
+                .just_pressed(PlatformerAction::Menus)
+        })
+    {
+        commands.insert_resource(PauseScreen::Inventory(
+            player,
+        ));
+        commands
+            .insert_resource(NextState(GameState::Paused));
+    }
+}
+
+#[derive(Clone, Copy, Debug, Component)]
+struct InventoryMenu {
+    player: Entity,
+}
+
+#[derive(Clone, Copy, Debug, Component)]
+struct InventoryItem(Item);
+
+#[derive(Clone, Copy, Debug, Component)]
+struct CloseInventory;
+
+#[derive(Clone, Copy, Debug, Component)]
+struct ItemDescription;
+
+fn spawn_inventory(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    screen: Res<PauseScreen>,
+    registry: Res<ItemRegistry>,
+    players: Query<(&Items, &Equipment)>,
+) {
+    if let PauseScreen::Inventory(player) = *screen {
+        if let Ok((items, equipment)) = players.get(player)
+        {
+            spawn_inventory_menu(
+                &mut commands,
+                &asset_server,
+                &registry,
+                player,
+                items,
+                equipment,
+                0,
+            );
+        }
+    }
+}
+
+fn spawn_inventory_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    registry: &ItemRegistry,
+    player: Entity,
+    items: &Items,
+    equipment: &Equipment,
+    selected: usize,
+) {
+    let mut len = 0;
+    let menu = spawn_menu(
+        commands,
+        asset_server,
+        "Inventory",
+        1,
+        InventoryMenu { player },
+        |menu| {
+            len = add_item_entries(
+                menu, registry, items, equipment,
+            );
+            menu.item("Close", CloseInventory);
+            menu.label("", ItemDescription);
+        },
+    );
+    // keep the cursor in place when the menu is
+    // rebuilt after using an item
+    commands.entity(menu).insert(Menu {
+        selected: selected.min(len),
+        len: len + 1,
+        depth: 1,
+    });
+}
+
+/// One entry per kind of item, with a count and
+/// whether it's equipped. Returns how many entries
+/// were added.
+fn add_item_entries(
+    menu: &mut MenuBuilder,
+    registry: &ItemRegistry,
+    items: &Items,
+    equipment: &Equipment,
+) -> usize {
+    let mut counts = BTreeMap::new();
+    for item in items.0.iter() {
+        *counts.entry(*item).or_insert(0) += 1;
+    }
+    for (item, count) in counts.iter() {
+        let mut label =
+            registry.get(*item).name.to_string();
+        if *count > 1 {
+            label += &format!(" x{count}");
+        }
+        if equipment.is_equipped(*item) {
+            label += " (equipped)";
+        }
+        menu.item(label, InventoryItem(*item));
+    }
+    counts.len()
+}
+
+fn despawn_inventory(
+    mut commands: Commands,
+    menus: Query<Entity, With<InventoryMenu>>,
+) {
+    for menu in menus.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
+
+fn inventory_actions(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    registry: Res<ItemRegistry>,
+    menu_actions: Res<ActionState<PlatformerAction>>,
+    mut confirmed: EventReader<MenuConfirmed>,
+    mut back: EventReader<MenuBack>,
+    menus: Query<(Entity, &InventoryMenu, &Menu)>,
+    items: Query<(&InventoryItem, &Parent)>,
+    close: Query<(), With<CloseInventory>>,
+    mut players: Query<(
+        &mut Items,
+        &mut Equipment,
+        Option<&mut Health>,
+    )>,
+    mut healed: EventWriter<Healed>,
+) {
+    let mut close_inventory = menus.iter().next().is_some()
+        && menu_actions
+            .just_pressed(PlatformerAction::Menus);
+    for MenuBack(menu) in back.iter() {
+        close_inventory |= menus.contains(*menu);
+    }
+
+    for MenuConfirmed(entry) in confirmed.iter() {
+        if close.contains(*entry) {
+            close_inventory = true;
+            continue;
+        }
+        let (InventoryItem(item), parent) =
+            match items.get(*entry) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+        let (menu, inventory, selection) =
+            match menus.get(parent.get()) {
+                Ok(menu) => menu,
+                Err(_) => continue,
+            };
+        let (mut player_items, mut equipment, health) =
+            match players.get_mut(inventory.player) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+
+        match registry.get(*item).kind {
+            ItemKind::Equipment(slot) => {
+                equipment.toggle(slot, *item);
+            }
+            ItemKind::Consumable(effect) => {
+                if let Some(index) = player_items
+                    .0
+                    .iter()
+                    .position(|held| held == item)
+                {
+                    player_items.0.remove(index);
+                    apply_effect(
+                        effect,
+                        inventory.player,
+                        health,
+                        &mut healed,
+                    );
+                }
+            }
+            ItemKind::Quest => continue,
+        }
+
+        commands.entity(menu).despawn_recursive();
+        spawn_inventory_menu(
+            &mut commands,
+            &asset_server,
+            &registry,
+            inventory.player,
+            &player_items,
+            &equipment,
+            selection.selected,
+        );
+    }
+
+    if close_inventory {
+        commands
+            .insert_resource(NextState(GameState::Playing));
+    }
+}
+
+fn apply_effect(
+    effect: Effect,
+    player: Entity,
+    health: Option<Mut<Health>>,
+    healed: &mut EventWriter<Healed>,
+) {
+    match effect {
+        Effect::Heal(amount) => {
+            if let Some(mut health) = health {
+                let before = health.current;
+                health.current = (health.current + amount)
+                    .min(health.max);
+                if health.current > before {
+                    healed.send(Healed {
+                        target: player,
+                        amount: health.current - before,
+                        current: health.current,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn describe_selected_item(
+    registry: Res<ItemRegistry>,
+    menus: Query<
+        &Menu,
+        (With<InventoryMenu>, Changed<Menu>),
+    >,
+    items: Query<(&InventoryItem, &MenuItem)>,
+    mut descriptions: Query<
+        (&mut Text, &Parent),
+        With<ItemDescription>,
+    >,
+) {
+    for (mut text, parent) in descriptions.iter_mut() {
+        let menu = match menus.get(parent.get()) {
+            Ok(menu) => menu,
+            Err(_) => continue,
+        };
+        let description = items
+            .iter()
+            .find(|(_, entry)| {
+                entry.menu == parent.get()
+                    && entry.index == menu.selected
+            })
+            .map_or("", |(InventoryItem(item), _)| {
+                registry.get(*item).description
+            });
+        text.sections[0].value = description.to_string();
+    }
+}
@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    actions::PlatformerAction,
+    bindings::{InputProfiles, InputSource},
+    gamepad::{source_just_pressed, PlayerSlots},
+    menu::MENU_FONT,
+    GameState,
+};
+
+/// The "press Start to join" screen shown before
+/// the level starts
+pub struct JoinPlugin;
+
+impl Plugin for JoinPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_enter_system(
+            GameState::Joining,
+            spawn_join_screen,
+        )
+        .add_exit_system(
+            GameState::Joining,
+            despawn_join_screen,
+        )
+        .add_system(
+            list_joined_players
+                .run_in_state(GameState::Joining),
+        )
+        .add_system(
+            start_game.run_in_state(GameState::Joining),
+        );
+    }
+}
+
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct JoinScreen;
+
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct JoinedList;
+
+fn spawn_join_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let font = asset_server.load(MENU_FONT);
+    let text = |value: &str, font_size: f32| {
+        TextBundle::from_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::all(Val::Px(12.)),
+            ..default()
+        })
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(
+                        Val::Percent(100.),
+                        Val::Percent(100.),
+                    ),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            JoinScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(text("Press Start to join", 56.));
+            parent.spawn((text("", 32.), JoinedList));
+            parent.spawn(text(
+                "Keyboard: Enter for WASD, Backspace for the arrow keys",
+                20.,
+            ));
+            parent.spawn(text("Jump to begin", 24.));
+        });
+}
+
+fn despawn_join_screen(
+    mut commands: Commands,
+    screens: Query<Entity, With<JoinScreen>>,
+) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+fn list_joined_players(
+    slots: Res<PlayerSlots>,
+    mut lists: Query<&mut Text, With<JoinedList>>,
+) {
+    if !slots.is_changed() {
+        return;
+    }
+    let value = slots
+        .0
+        .iter()
+        .enumerate()
+        .map(|(index, slot)| {
+            let source = match slot.source {
+                InputSource::Gamepad(gamepad) => {
+                    format!("Gamepad {}", gamepad.id)
+                }
+                InputSource::KeyboardLeft => {
+                    "Keyboard (WASD)".to_string()
+                }
+                InputSource::KeyboardRight => {
+                    "Keyboard (arrows)".to_string()
+                }
+            };
+            let unplugged = if slot.connected {
+                ""
+            } else {
+                " (unplugged)"
+            };
+            format!(
+                "Player {}: {source}{unplugged}",
+                index + 1
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    for mut text in lists.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+/// Any player who joined can start the game
+fn start_game(
+    mut commands: Commands,
+    slots: Res<PlayerSlots>,
+    profiles: Res<InputProfiles>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+) {
+    let start = slots.0.iter().any(|slot| {
+        slot.connected
+            && source_just_pressed(
+                slot.source,
+                PlatformerAction::Jump,
+                &profiles,
+                &keys,
+                &buttons,
+            )
+    });
+    if start {
+        commands.insert_resource(NextState(
+            GameState::LevelLoading,
+        ));
+    }
+}
@@ -0,0 +1,307 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    camera::gameplay_frozen,
+    movement::{State, StateTransition, TIMESTEP},
+    GameState,
+};
+
+/// Tuning for the player's squash and stretch
+pub const JUICE_SETTINGS: &str = "player.juice.json";
+
+/// Squash, stretch and lean on the player's
+/// [`PlayerVisual`], which never touches the
+/// physics `Transform`
+pub struct JuicePlugin;
+
+impl Plugin for JuicePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<JuiceSettings>()
+            .init_asset_loader::<JuiceSettingsLoader>()
+            .init_resource::<JuiceSettings>()
+            .add_startup_system(load_juice_settings)
+            .add_system(apply_juice_settings)
+            .add_system(
+                juice_from_transitions
+                    .run_in_state(GameState::Playing),
+            )
+            .add_system(
+                juice_from_movement
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen),
+            )
+            .add_system(
+                animate_juice
+                    .after(juice_from_transitions)
+                    .after(juice_from_movement),
+            );
+    }
+}
+
+/// A damped spring pulling back to rest
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Spring {
+    pub stiffness: f32,
+    pub damping: f32,
+}
+
+impl Spring {
+    /// How quickly something `offset` from rest and
+    /// moving at `velocity` speeds up
+    fn acceleration(
+        &self,
+        offset: f32,
+        velocity: f32,
+    ) -> f32 {
+        -self.stiffness * offset - self.damping * velocity
+    }
+}
+
+/// Loaded from [`JUICE_SETTINGS`], any field left
+/// out keeps its default
+#[derive(Clone, Debug, Deserialize, Resource, TypeUuid)]
+#[uuid = "43f49ee3-8b04-45dc-b3eb-30c7907e1b1d"]
+#[serde(default)]
+pub struct JuiceSettings {
+    /// How much taller and thinner the player gets
+    /// on takeoff, as a fraction of their size
+    pub takeoff_stretch: f32,
+    /// How much shorter and wider the player gets
+    /// on landing
+    pub landing_squash: f32,
+    pub stretch_spring: Spring,
+    /// Radians of lean per px/s the horizontal
+    /// speed changes by in a frame
+    pub lean: f32,
+    /// The furthest the player leans, in radians
+    pub max_lean: f32,
+    /// Radians per second of spin given by running
+    /// into a wall
+    pub wall_wobble: f32,
+    pub lean_spring: Spring,
+    /// How far below the visual's origin the
+    /// player's feet are, so squashing keeps them on
+    /// the ground
+    pub feet: f32,
+}
+
+impl Default for JuiceSettings {
+    fn default() -> Self {
+        Self {
+            takeoff_stretch: 0.25,
+            landing_squash: 0.3,
+            stretch_spring: Spring {
+                stiffness: 300.,
+                damping: 12.,
+            },
+            lean: 0.01,
+            max_lean: 0.25,
+            wall_wobble: 6.,
+            lean_spring: Spring {
+                stiffness: 200.,
+                damping: 6.,
+            },
+            feet: 24.,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct JuiceSettingsLoader;
+
+impl AssetLoader for JuiceSettingsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>>
+    {
+        Box::pin(async move {
+            let settings: JuiceSettings =
+                serde_json::from_slice(bytes)?;
+            load_context.set_default_asset(
+                LoadedAsset::new(settings),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["juice.json"]
+    }
+}
+
+#[derive(Debug, Resource)]
+struct JuiceSettingsHandle(Handle<JuiceSettings>);
+
+fn load_juice_settings(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(JuiceSettingsHandle(
+        asset_server.load(JUICE_SETTINGS),
+    ));
+}
+
+/// Keeps [`JuiceSettings`] in step with the file,
+/// including when it's edited while running
+fn apply_juice_settings(
+    mut events: EventReader<AssetEvent<JuiceSettings>>,
+    assets: Res<Assets<JuiceSettings>>,
+    handle: Option<Res<JuiceSettingsHandle>>,
+    mut settings: ResMut<JuiceSettings>,
+) {
+    let handle = match handle {
+        Some(handle) => handle,
+        None => return,
+    };
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle: changed }
+            | AssetEvent::Modified { handle: changed }
+                if *changed == handle.0 =>
+            {
+                if let Some(loaded) = assets.get(changed) {
+                    *settings = loaded.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The child that draws a player. Its transform is
+/// purely cosmetic.
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+pub struct PlayerVisual;
+
+/// How far a [`PlayerVisual`] is squashed and
+/// leaning, and how fast that's changing
+#[derive(Clone, Debug, Default, Component)]
+pub struct Juice {
+    /// Added to the visual's scale, so zero is at
+    /// rest
+    pub stretch: Vec2,
+    pub stretch_velocity: Vec2,
+    /// In radians, positive leans left
+    pub lean: f32,
+    pub lean_velocity: f32,
+    last_speed: f32,
+    touching_wall: bool,
+}
+
+impl Juice {
+    /// Taller and thinner for a positive `amount`,
+    /// shorter and wider for a negative one
+    pub fn pop(&mut self, amount: f32) {
+        self.stretch = Vec2::new(-amount, amount);
+        self.stretch_velocity = Vec2::ZERO;
+    }
+}
+
+fn juice_from_transitions(
+    settings: Res<JuiceSettings>,
+    mut transitions: EventReader<StateTransition>,
+    mut visuals: Query<(&Parent, &mut Juice)>,
+) {
+    for transition in transitions.iter() {
+        let amount =
+            match (&transition.from, &transition.to) {
+                (from, to) if from == to => continue,
+                (_, State::Jumping {}) => {
+                    settings.takeoff_stretch
+                }
+                (
+                    State::Jumping {} | State::Falling {},
+                    State::Idle {},
+                ) => -settings.landing_squash,
+                _ => continue,
+            };
+        for (parent, mut juice) in visuals.iter_mut() {
+            if parent.get() == transition.entity {
+                juice.pop(amount);
+            }
+        }
+    }
+}
+
+/// Leans into changes of speed, and wobbles when
+/// running into a wall
+fn juice_from_movement(
+    settings: Res<JuiceSettings>,
+    players: Query<&KinematicCharacterControllerOutput>,
+    mut visuals: Query<(&Parent, &mut Juice)>,
+) {
+    for (parent, mut juice) in visuals.iter_mut() {
+        let output = match players.get(parent.get()) {
+            Ok(output) => output,
+            Err(_) => continue,
+        };
+        let speed =
+            output.effective_translation.x / TIMESTEP;
+        juice.lean_velocity -=
+            (speed - juice.last_speed) * settings.lean;
+        juice.last_speed = speed;
+
+        let touching_wall =
+            output.collisions.iter().any(|collision| {
+                collision.toi.normal1.x.abs() > 0.7
+            });
+        if touching_wall && !juice.touching_wall {
+            // rock back, away from the wall
+            juice.lean_velocity += settings.wall_wobble
+                * output.desired_translation.x.signum();
+        }
+        juice.touching_wall = touching_wall;
+    }
+}
+
+fn animate_juice(
+    time: Res<Time>,
+    settings: Res<JuiceSettings>,
+    mut visuals: Query<(&mut Juice, &mut Transform)>,
+) {
+    let dt = time.delta_seconds();
+    for (mut juice, mut transform) in visuals.iter_mut() {
+        let juice = &mut *juice;
+        let spring = settings.stretch_spring;
+        juice.stretch_velocity += Vec2::new(
+            spring.acceleration(
+                juice.stretch.x,
+                juice.stretch_velocity.x,
+            ),
+            spring.acceleration(
+                juice.stretch.y,
+                juice.stretch_velocity.y,
+            ),
+        ) * dt;
+        juice.stretch += juice.stretch_velocity * dt;
+
+        juice.lean_velocity += settings
+            .lean_spring
+            .acceleration(juice.lean, juice.lean_velocity)
+            * dt;
+        juice.lean = (juice.lean
+            + juice.lean_velocity * dt)
+            .clamp(-settings.max_lean, settings.max_lean);
+
+        transform.scale =
+            (Vec2::ONE + juice.stretch).extend(1.);
+        transform.rotation =
+            Quat::from_rotation_z(juice.lean);
+        // scaling is about the center, so move back
+        // down onto the feet
+        transform.translation.y =
+            settings.feet * juice.stretch.y;
+    }
+}
@@ -1,13 +1,54 @@
+// Bevy systems take their queries and resources as
+// arguments, so these fire on most of them
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
 pub mod actions;
+pub mod animation;
+pub mod aseprite;
+pub mod attack;
+pub mod bindings;
+pub mod camera;
+pub mod camera_shake;
+pub mod checkpoint;
+pub mod combat;
 pub mod components;
+pub mod config;
+pub mod enemy;
 pub mod gamepad;
+pub mod hud;
+pub mod items;
+pub mod join;
+pub mod juice;
+pub mod loading;
+pub mod menu;
 pub mod movement;
+pub mod navigation;
+pub mod particles;
+pub mod pause;
+pub mod pixel_perfect;
+pub mod projectile;
+pub mod replay;
+pub mod save;
+pub mod settings;
 pub mod systems;
+pub mod title;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 pub enum GameState {
     AssetLoading,
+    MainMenu,
+    /// Picking which level to start from
+    LevelSelect,
+    /// Waiting for players to press Start
+    Joining,
+    /// Waiting for the levels and their colliders to
+    /// spawn, see [`loading`]
+    LevelLoading,
     Playing,
+    /// Gameplay is frozen behind the pause menu
+    Paused,
+    /// Every player is out of health
+    GameOver,
 }
 
 // State Machine
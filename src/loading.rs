@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use iyes_progress::{prelude::*, ProgressSystemLabel};
+
+use crate::{
+    camera::{
+        gameplay_frozen, LevelTransition,
+        LevelTransitionSettings,
+    },
+    combat::HitStop,
+    components::WallColliders,
+    menu::MENU_FONT,
+    GameState,
+};
+
+/// The IntGrid value walls are registered under
+const WALL_VALUE: i32 = 1;
+const BAR_SIZE: Vec2 = Vec2::new(400., 16.);
+const BAR_BACKGROUND: Color = Color::rgb(0.2, 0.2, 0.2);
+const BAR_FILL: Color = Color::rgb(1., 0.8, 0.2);
+
+/// The loading screen, and waiting in
+/// [`GameState::LevelLoading`] until the levels and
+/// their wall colliders have spawned
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(
+            ProgressPlugin::new(GameState::LevelLoading)
+                .continue_to(GameState::Playing),
+        )
+        .add_enter_system(
+            GameState::AssetLoading,
+            spawn_loading_screen,
+        )
+        .add_exit_system(
+            GameState::AssetLoading,
+            despawn_loading_screen,
+        )
+        .add_enter_system(
+            GameState::LevelLoading,
+            spawn_loading_screen,
+        )
+        .add_exit_system(
+            GameState::LevelLoading,
+            despawn_loading_screen,
+        )
+        // nothing should fall before there's a floor
+        // to land on
+        .add_enter_system(
+            GameState::LevelLoading,
+            pause_physics,
+        )
+        .add_exit_system(
+            GameState::LevelLoading,
+            resume_physics,
+        )
+        .add_system(
+            level_progress
+                .track_progress()
+                .run_in_state(GameState::LevelLoading),
+        )
+        .add_system(
+            draw_progress
+                .after(ProgressSystemLabel::Tracking),
+        );
+    }
+}
+
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct LoadingScreen;
+
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct LoadingBar;
+
+fn spawn_loading_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(
+                        Val::Percent(100.),
+                        Val::Percent(100.),
+                    ),
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                z_index: ZIndex::Global(200),
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    "Loading",
+                    TextStyle {
+                        font: asset_server.load(MENU_FONT),
+                        font_size: 56.,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::bottom(Val::Px(24.)),
+                    ..default()
+                }),
+            );
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(
+                            Val::Px(BAR_SIZE.x),
+                            Val::Px(BAR_SIZE.y),
+                        ),
+                        ..default()
+                    },
+                    background_color: BAR_BACKGROUND.into(),
+                    ..default()
+                })
+                .with_children(|bar| {
+                    bar.spawn((
+                        NodeBundle {
+                            style: Style {
+                                size: Size::new(
+                                    Val::Percent(0.),
+                                    Val::Percent(100.),
+                                ),
+                                ..default()
+                            },
+                            background_color: BAR_FILL
+                                .into(),
+                            ..default()
+                        },
+                        LoadingBar,
+                    ));
+                });
+        });
+}
+
+fn despawn_loading_screen(
+    mut commands: Commands,
+    screens: Query<Entity, With<LoadingScreen>>,
+) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+fn pause_physics(
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    rapier_config.physics_pipeline_active = false;
+}
+
+fn resume_physics(
+    mut rapier_config: ResMut<RapierConfiguration>,
+    transition: Option<Res<LevelTransition>>,
+    settings: Option<Res<LevelTransitionSettings>>,
+    hit_stop: Option<Res<HitStop>>,
+) {
+    rapier_config.physics_pipeline_active =
+        !gameplay_frozen(transition, settings, hit_stop);
+}
+
+/// Two steps per level: spawning it, then giving its
+/// walls colliders. Levels without walls skip the
+/// second.
+fn level_progress(
+    levels: Query<(
+        &Handle<LdtkLevel>,
+        Option<&Children>,
+        Option<&WallColliders>,
+    )>,
+    level_assets: Res<Assets<LdtkLevel>>,
+) -> Progress {
+    // the world hasn't made its levels yet
+    if levels.is_empty() {
+        return false.into();
+    }
+    let mut progress = Progress::default();
+    for (handle, children, colliders) in levels.iter() {
+        progress.total += 2;
+        if children.is_none() {
+            continue;
+        }
+        progress.done += 1;
+        let has_walls =
+            level_assets.get(handle).is_none_or(|level| {
+                level
+                    .level
+                    .layer_instances
+                    .iter()
+                    .flatten()
+                    .any(|layer| {
+                        layer
+                            .int_grid_csv
+                            .contains(&WALL_VALUE)
+                    })
+            });
+        if !has_walls || colliders.is_some() {
+            progress.done += 1;
+        }
+    }
+    progress
+}
+
+fn draw_progress(
+    progress: Option<Res<ProgressCounter>>,
+    mut bars: Query<&mut Style, With<LoadingBar>>,
+) {
+    let progress = match progress {
+        Some(progress) => progress.progress(),
+        None => return,
+    };
+    let fraction = if progress.total == 0 {
+        0.
+    } else {
+        f32::from(progress)
+    };
+    for mut style in bars.iter_mut() {
+        style.size.width = Val::Percent(fraction * 100.);
+    }
+}
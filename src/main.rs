@@ -1,39 +1,109 @@
-use bevy::prelude::*;
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+};
 use bevy_asset_loader::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_rapier2d::prelude::*;
 use iyes_loopless::prelude::*;
-use iyes_progress::{ProgressCounter, ProgressPlugin};
+use iyes_progress::ProgressPlugin;
 use leafwing_input_manager::prelude::*;
 use platformer::{
     actions::PlatformerAction,
+    animation::AnimationPlugin,
+    attack::AttackPlugin,
+    bindings::BindingsPlugin,
+    camera::{CameraPlugin, MainCamera},
+    camera_shake::CameraShakePlugin,
+    checkpoint::CheckpointPlugin,
+    combat::CombatPlugin,
+    enemy::EnemyPlugin,
     components::{self, GroundDetection},
     gamepad::GamepadPlugin,
-    movement::MovementPlugin,
-    systems, GameState,
+    hud::HudPlugin,
+    items::ItemsPlugin,
+    join::JoinPlugin,
+    juice::JuicePlugin,
+    loading::LoadingPlugin,
+    menu::MenuPlugin,
+    movement::{MovementPlugin, TIMESTEP},
+    navigation::NavigationPlugin,
+    particles::ParticlesPlugin,
+    pause::PausePlugin,
+    pixel_perfect::PixelPerfectPlugin,
+    projectile::ProjectilePlugin,
+    replay::ReplayPlugin,
+    save::SavePlugin,
+    settings::{Settings, SettingsPlugin},
+    systems,
+    title::TitlePlugin,
+    GameState,
 };
 
 fn main() {
+    // read before the window is made, so it opens
+    // with the saved mode and size
+    let settings = Settings::load();
     let mut app = App::new();
     app.add_loopless_state(GameState::AssetLoading);
     LoadingState::new(GameState::AssetLoading)
         // https://github.com/NiklasEi/bevy_asset_loader/issues/54
-        .continue_to_state(GameState::Playing)
+        .continue_to_state(GameState::MainMenu)
         .with_collection::<ImageAssets>()
         .build(&mut app);
 
-    app.add_plugins(DefaultPlugins)
+    app.add_plugins(
+        DefaultPlugins
+            .set(AssetPlugin {
+                watch_for_changes: cfg!(feature = "hot_reload"),
+                ..default()
+            })
+            .set(WindowPlugin {
+                window: settings.window_descriptor(),
+                ..default()
+            }),
+    )
+        .insert_resource(settings)
+        .add_plugin(SettingsPlugin)
         .add_plugin(ProgressPlugin::new(
             GameState::AssetLoading,
         ))
-        .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
+        .add_plugin(LoadingPlugin)
+        // step rapier in lockstep with the player
+        // simulation, see `movement::SIMULATION`
+        .insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: TIMESTEP,
+                substeps: 1,
+            },
+            ..default()
+        })
+        .add_plugin(
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0)
+                .with_default_system_setup(false),
+        )
         .add_plugin(RapierDebugRenderPlugin::default())
         .add_plugin(LdtkPlugin)
         .add_plugin(MovementPlugin)
+        .add_plugin(AnimationPlugin)
+        .add_plugin(JuicePlugin)
+        .add_plugin(ParticlesPlugin)
+        .add_plugin(CheckpointPlugin)
+        .add_plugin(EnemyPlugin)
+        .add_plugin(NavigationPlugin)
+        .add_plugin(CombatPlugin)
+        .add_plugin(AttackPlugin)
+        .add_plugin(ProjectilePlugin)
+        .add_plugin(CameraPlugin)
+        .add_plugin(PixelPerfectPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(CameraShakePlugin)
         .add_plugin(
             InputManagerPlugin::<PlatformerAction>::default(
             ),
         )
+        .add_plugin(MenuPlugin)
+        .add_plugin(BindingsPlugin)
         .insert_resource(LdtkSettings {
             level_spawn_behavior:
                 LevelSpawnBehavior::UseWorldTranslation {
@@ -49,27 +119,43 @@ fn main() {
         .insert_resource(GroundDetection {
             on_ground: false,
         })
-        .add_enter_system(GameState::Playing, setup)
-        .add_system(
-            systems::camera_fit_inside_current_level,
-        )
-        // .add_system(systems::pause_physics_during_load)
+        .add_enter_system(GameState::LevelLoading, setup)
         .add_system(systems::spawn_wall_collision)
         // .add_system(systems::movement)
-        .add_system(systems::patrol)
+        .add_system(
+            systems::patrol.run_in_state(GameState::Playing),
+        )
         .add_system(systems::update_level_selection)
         .add_system(systems::spawn_ground_sensor)
         .register_ldtk_entity::<components::PlayerBundle>(
             "Player",
         )
-        .add_system(systems::restart_level)
+        .register_ldtk_entity::<components::CameraZoneBundle>(
+            "CameraZone",
+        )
+        .register_ldtk_entity::<components::PickupBundle>(
+            "Pickup",
+        )
+        .register_ldtk_entity::<components::CheckpointBundle>(
+            "Checkpoint",
+        )
+        .register_ldtk_entity::<components::MobBundle>("Mob")
+        .register_ldtk_entity::<components::TurretBundle>(
+            "Turret",
+        )
+        .add_system(
+            systems::restart_level
+                .run_in_state(GameState::Playing),
+        )
         .add_system(systems::player_added)
         .register_ldtk_int_cell::<components::WallBundle>(1)
         .add_plugin(GamepadPlugin)
-        .add_system_to_stage(
-            CoreStage::PostUpdate,
-            print_progress,
-        )
+        .add_plugin(JoinPlugin)
+        .add_plugin(PausePlugin)
+        .add_plugin(TitlePlugin)
+        .add_plugin(ItemsPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(SavePlugin)
         .run();
 }
 
@@ -81,19 +167,23 @@ struct ImageAssets {
 
 fn setup(mut commands: Commands, images: Res<ImageAssets>) {
     // camera.orthographic_projection.scale = 2.;
-    commands.spawn(Camera2dBundle::default());
+    commands.spawn((
+        Camera2dBundle {
+            // the letterbox camera clears the screen
+            // before this camera draws into its
+            // viewport
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
+            ..default()
+        },
+        // drawn by the `UiCamera` instead
+        UiCameraConfig { show_ui: false },
+        MainCamera,
+    ));
 
     commands.spawn(LdtkWorldBundle {
         ldtk_handle: images.map.clone(),
         ..Default::default()
     });
 }
-
-fn print_progress(progress: Option<Res<ProgressCounter>>) {
-    if let Some(progress) = progress {
-        info!(
-            "Current progress: {:?}",
-            progress.progress()
-        );
-    }
-}
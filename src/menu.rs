@@ -0,0 +1,263 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::actions::PlatformerAction;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActionState<PlatformerAction>>(
+        )
+        .add_event::<MenuConfirmed>()
+        .add_event::<MenuBack>()
+        .add_system(
+            navigate_menus.label(MenuSystem::Navigate),
+        )
+        .add_system(
+            highlight_selected.after(MenuSystem::Navigate),
+        );
+    }
+}
+
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+pub enum MenuSystem {
+    Navigate,
+}
+
+pub const MENU_FONT: &str = "fonts/FiraSans-Bold.ttf";
+
+const SELECTED_COLOR: Color = Color::rgb(1., 0.8, 0.2);
+const UNSELECTED_COLOR: Color = Color::WHITE;
+
+/// A list of items navigated with the
+/// [`PlatformerAction`] resource: `Up` and `Down`
+/// to move, `Jump` to confirm and `Pause` to go
+/// back.
+///
+/// When several menus are open only the deepest one
+/// takes input.
+#[derive(Clone, Debug, Default, Component)]
+pub struct Menu {
+    pub selected: usize,
+    pub len: usize,
+    pub depth: i32,
+}
+
+/// An entry in a [`Menu`], holding its position in
+/// the list
+#[derive(Clone, Copy, Debug, Component)]
+pub struct MenuItem {
+    pub menu: Entity,
+    pub index: usize,
+}
+
+/// Sent with the selected [`MenuItem`]'s entity when
+/// it is confirmed
+#[derive(Clone, Copy, Debug)]
+pub struct MenuConfirmed(pub Entity);
+
+/// Sent with the [`Menu`]'s entity when backing out
+/// of it
+#[derive(Clone, Copy, Debug)]
+pub struct MenuBack(pub Entity);
+
+/// Fills a [`Menu`] with items
+pub struct MenuBuilder<'w, 's, 'a, 'b> {
+    parent: &'b mut ChildBuilder<'w, 's, 'a>,
+    menu: Entity,
+    font: Handle<Font>,
+    len: usize,
+}
+
+impl<'w, 's, 'a, 'b> MenuBuilder<'w, 's, 'a, 'b> {
+    /// Adds an item with the given label, plus any
+    /// components the menu uses to tell its items
+    /// apart
+    pub fn item(
+        &mut self,
+        label: impl Into<String>,
+        bundle: impl Bundle,
+    ) -> Entity {
+        let index = self.len;
+        self.len += 1;
+        self.parent
+            .spawn((
+                TextBundle::from_section(
+                    label,
+                    TextStyle {
+                        font: self.font.clone(),
+                        font_size: 32.,
+                        color: UNSELECTED_COLOR,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(6.)),
+                    ..default()
+                }),
+                MenuItem {
+                    menu: self.menu,
+                    index,
+                },
+                bundle,
+            ))
+            .id()
+    }
+
+    /// Adds text that can't be selected
+    pub fn label(
+        &mut self,
+        text: impl Into<String>,
+        bundle: impl Bundle,
+    ) -> Entity {
+        self.parent
+            .spawn((
+                TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font: self.font.clone(),
+                        font_size: 24.,
+                        color: Color::GRAY,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(6.)),
+                    ..default()
+                }),
+                bundle,
+            ))
+            .id()
+    }
+}
+
+/// Spawns a full screen [`Menu`] with a title.
+///
+/// `bundle` is added to the menu's root entity, so
+/// it can be found and despawned later.
+pub fn spawn_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    title: &str,
+    depth: i32,
+    bundle: impl Bundle,
+    build: impl FnOnce(&mut MenuBuilder),
+) -> Entity {
+    let font = asset_server.load(MENU_FONT);
+    let mut root = commands.spawn((
+        NodeBundle {
+            style: Style {
+                size: Size::new(
+                    Val::Percent(100.),
+                    Val::Percent(100.),
+                ),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.75)
+                .into(),
+            z_index: ZIndex::Global(100 + depth),
+            ..default()
+        },
+        bundle,
+    ));
+    let menu = root.id();
+
+    let mut len = 0;
+    root.with_children(|parent| {
+        parent.spawn(
+            TextBundle::from_section(
+                title,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 56.,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::bottom(Val::Px(24.)),
+                ..default()
+            }),
+        );
+
+        let mut builder = MenuBuilder {
+            parent,
+            menu,
+            font,
+            len: 0,
+        };
+        build(&mut builder);
+        len = builder.len;
+    });
+
+    commands.entity(menu).insert(Menu {
+        selected: 0,
+        len,
+        depth,
+    });
+    menu
+}
+
+fn navigate_menus(
+    action_state: Res<ActionState<PlatformerAction>>,
+    mut menus: Query<(Entity, &mut Menu)>,
+    items: Query<(Entity, &MenuItem)>,
+    mut confirmed: EventWriter<MenuConfirmed>,
+    mut back: EventWriter<MenuBack>,
+) {
+    let (entity, mut menu) = match menus
+        .iter_mut()
+        .max_by_key(|(_, menu)| menu.depth)
+    {
+        Some(menu) => menu,
+        None => return,
+    };
+
+    if menu.len > 0 {
+        if action_state.just_pressed(PlatformerAction::Down)
+        {
+            menu.selected = (menu.selected + 1) % menu.len;
+        }
+        if action_state.just_pressed(PlatformerAction::Up) {
+            menu.selected =
+                (menu.selected + menu.len - 1) % menu.len;
+        }
+    }
+
+    if action_state.just_pressed(PlatformerAction::Jump) {
+        if let Some((item, _)) =
+            items.iter().find(|(_, item)| {
+                item.menu == entity
+                    && item.index == menu.selected
+            })
+        {
+            confirmed.send(MenuConfirmed(item));
+        }
+    } else if action_state
+        .just_pressed(PlatformerAction::Pause)
+    {
+        back.send(MenuBack(entity));
+    }
+}
+
+fn highlight_selected(
+    menus: Query<&Menu, Changed<Menu>>,
+    mut items: Query<(&MenuItem, &mut Text)>,
+) {
+    for (item, mut text) in items.iter_mut() {
+        if let Ok(menu) = menus.get(item.menu) {
+            let color = if item.index == menu.selected {
+                SELECTED_COLOR
+            } else {
+                UNSELECTED_COLOR
+            };
+            for section in text.sections.iter_mut() {
+                section.style.color = color;
+            }
+        }
+    }
+}
@@ -1,36 +1,271 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
-use iyes_loopless::prelude::IntoConditionalSystem;
-use leafwing_input_manager::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::{
+    plugin::InputManagerSystem, prelude::*,
+};
 use statig::{
     prelude::*, InitializedStatemachine, StateOrSuperstate,
 };
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
 // use crate::components::{Climber,
 // GroundDetection, Player};
-use crate::{actions::*, GameState};
+use crate::{
+    actions::*, camera::gameplay_frozen, GameState,
+};
 
 pub struct MovementPlugin;
 
 impl Plugin for MovementPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(
-            jump.run_in_state(GameState::Playing),
+        app.init_resource::<SimulationClock>()
+            .add_fixed_timestep_before_stage(
+                CoreStage::Update,
+                Duration::from_secs_f32(TIMESTEP),
+                SIMULATION,
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                latch_simulation_input
+                    .run_in_state(GameState::Playing)
+                    .after(InputManagerSystem::Update),
+            )
+            .add_fixed_timestep_system(
+                SIMULATION,
+                SIMULATION_GAMEPLAY,
+                machine_events
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .label(PlayerSimulation)
+                    .label(MovementSystem::StateMachine),
+            )
+            .add_fixed_timestep_system(
+                SIMULATION,
+                SIMULATION_GAMEPLAY,
+                jump.run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .label(PlayerSimulation)
+                    .label(MovementSystem::Jump)
+                    .after(MovementSystem::StateMachine),
+            )
+            .add_fixed_timestep_system(
+                SIMULATION,
+                SIMULATION_GAMEPLAY,
+                fall.run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .label(PlayerSimulation)
+                    .label(MovementSystem::Fall)
+                    .after(MovementSystem::Jump),
+            )
+            .add_fixed_timestep_system(
+                SIMULATION,
+                SIMULATION_GAMEPLAY,
+                horizontal
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .label(PlayerSimulation)
+                    .label(MovementSystem::Horizontal)
+                    .after(MovementSystem::Fall),
+            )
+            .add_fixed_timestep_system(
+                SIMULATION,
+                SIMULATION_GAMEPLAY,
+                dash.run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .label(PlayerSimulation)
+                    .after(MovementSystem::Horizontal),
+            );
+
+        // rapier steps along with the player, so its
+        // own stages have to be turned off with
+        // `with_default_system_setup(false)`
+        app.add_fixed_timestep_child_stage(SIMULATION)
+            .add_fixed_timestep_system_set(
+                SIMULATION,
+                1,
+                RapierPhysicsPlugin::<NoUserData>::get_systems(
+                    PhysicsStages::SyncBackend,
+                ),
+            )
+            .add_fixed_timestep_child_stage(SIMULATION)
+            .add_fixed_timestep_system_set(
+                SIMULATION,
+                2,
+                RapierPhysicsPlugin::<NoUserData>::get_systems(
+                    PhysicsStages::StepSimulation,
+                ),
+            )
+            .add_fixed_timestep_child_stage(SIMULATION)
+            .add_fixed_timestep_system_set(
+                SIMULATION,
+                3,
+                RapierPhysicsPlugin::<NoUserData>::get_systems(
+                    PhysicsStages::Writeback,
+                ),
+            )
+            .add_stage_before(
+                CoreStage::Last,
+                PhysicsStages::DetectDespawn,
+                SystemStage::parallel().with_system_set(
+                    RapierPhysicsPlugin::<NoUserData>::get_systems(
+                        PhysicsStages::DetectDespawn,
+                    ),
+                ),
+            );
+
+        // once everything has moved, the step is over
+        app.add_fixed_timestep_system(
+            SIMULATION,
+            3,
+            tick_simulation_clock
+                .run_in_state(GameState::Playing)
+                .run_if_not(gameplay_frozen),
         )
-        // // .add_system(Movement_input)
-        .add_system(
-            horizontal.run_in_state(GameState::Playing),
+        .add_fixed_timestep_system(
+            SIMULATION,
+            3,
+            consume_simulation_input
+                .run_in_state(GameState::Playing)
+                .run_if_not(gameplay_frozen),
         )
-        .add_system(fall.run_in_state(GameState::Playing))
-        .add_system(machine_events)
-        .add_system(debug_actions);
+        .add_fixed_timestep_system(
+            SIMULATION,
+            3,
+            send_transitions
+                .run_in_state(GameState::Playing)
+                .run_if_not(gameplay_frozen),
+        )
+        .add_system(debug_actions)
+        .add_event::<Landed>()
+        .add_event::<StateTransition>();
     }
 }
 
+/// The player simulation advances by exactly this
+/// much every step, rapier included, so the same
+/// inputs always give the same result whatever the
+/// frame rate.
+pub const TIMESTEP: f32 = 1. / 60.;
+
+/// The fixed timestep the player simulation and
+/// rapier run on. It runs before `CoreStage::Update`,
+/// so everything there sees where players ended up.
+pub const SIMULATION: &str = "simulation";
+/// The [`SIMULATION`] sub-stage for systems that move
+/// players. Rapier steps in the ones after it.
+pub const SIMULATION_GAMEPLAY: usize = 0;
+
+/// Systems that move players around. Anything
+/// feeding them input, like replays, runs before,
+/// and anything overriding their movement after.
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+pub struct PlayerSimulation;
+
+/// The order the player simulation runs in every
+/// step: state changes first, then each kind of
+/// movement, with later ones overriding earlier
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+pub enum MovementSystem {
+    StateMachine,
+    Jump,
+    Fall,
+    Horizontal,
+}
+
+/// The actions the player simulation sees. Presses
+/// and releases are held until a step has run with
+/// them, however many frames that takes.
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct SimulationInput(ActionState<PlatformerAction>);
+
+/// Copies live input over for the next step. A press
+/// no step has seen yet outlives its release.
+fn latch_simulation_input(
+    mut players: Query<
+        (
+            &ActionState<PlatformerAction>,
+            &mut SimulationInput,
+        ),
+        With<InputMap<PlatformerAction>>,
+    >,
+) {
+    for (action_state, mut input) in players.iter_mut() {
+        for action in PlatformerAction::variants() {
+            if action_state.pressed(action) {
+                input.press(action);
+            } else if !input.just_pressed(action) {
+                input.release(action);
+            }
+            let live = action_state.action_data(action);
+            let (value, axis_pair) =
+                (live.value, live.axis_pair);
+            let data = input.action_data_mut(action);
+            data.value = value;
+            data.axis_pair = axis_pair;
+        }
+    }
+}
+
+fn consume_simulation_input(
+    mut players: Query<&mut SimulationInput>,
+) {
+    for mut input in players.iter_mut() {
+        for action in PlatformerAction::variants() {
+            input.action_data_mut(action).state.tick();
+        }
+    }
+}
+
+/// Simulated time, counted in [`TIMESTEP`]s. Unlike
+/// `Time` it only moves on steps the player
+/// simulation runs.
+#[derive(Debug, Default, Resource)]
+pub struct SimulationClock {
+    pub steps: u64,
+}
+
+impl SimulationClock {
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(
+            self.steps as f64 * TIMESTEP as f64,
+        )
+    }
+}
+
+fn tick_simulation_clock(mut clock: ResMut<SimulationClock>) {
+    clock.steps += 1;
+}
+
+/// Sent when a player touches down after jumping
+/// or falling
+#[derive(Clone, Copy, Debug)]
+pub struct Landed {
+    pub entity: Entity,
+    /// How far the player fell before landing, in
+    /// world pixels
+    pub fall_height: f32,
+}
+
+/// Sent for every transition a player's state
+/// machine makes, in the order they happened
+#[derive(Clone, Debug)]
+pub struct StateTransition {
+    pub entity: Entity,
+    pub from: State,
+    pub to: State,
+}
+
 #[derive(Default)]
 struct PlayerStateMachine {
     last_jump: Option<Duration>,
+    /// Filled by `on_transition`, and drained into
+    /// [`StateTransition`] events each step
+    transitions: Vec<(State, State)>,
 }
 #[derive(Debug)]
 pub enum Event {
@@ -45,6 +280,18 @@ pub enum Event {
 pub struct PlayerState(
     InitializedStatemachine<PlayerStateMachine>,
 );
+impl PlayerState {
+    pub fn state(&self) -> &State {
+        self.0.state()
+    }
+
+    /// Launches the player into a fresh jump, like
+    /// when bouncing off an enemy
+    pub fn bounce(&mut self, now: Duration) {
+        self.0.last_jump = Some(now);
+        self.0.handle(&Event::Jump { event_time: now });
+    }
+}
 impl Default for PlayerState {
     fn default() -> Self {
         Self(
@@ -59,7 +306,7 @@ impl Default for PlayerState {
     initial = "State::idle()",
     on_dispatch = "Self::on_dispatch",
     on_transition = "Self::on_transition",
-    state(derive(Debug)),
+    state(derive(Debug, Clone, PartialEq)),
     superstate(derive(Debug))
 )]
 impl PlayerStateMachine {
@@ -72,6 +319,8 @@ impl PlayerStateMachine {
             "transitioned from `{:?}` to `{:?}`",
             source, target
         );
+        self.transitions
+            .push((source.clone(), target.clone()));
     }
 
     fn on_dispatch(
@@ -100,7 +349,7 @@ impl PlayerStateMachine {
     #[state]
     fn jumping(event: &Event) -> Response<State> {
         match event {
-            Event::Jump { event_time } => {
+            Event::Jump { .. } => {
                 Transition(State::jumping())
             }
             Event::Heal => Transition(State::healing()),
@@ -112,7 +361,7 @@ impl PlayerStateMachine {
     #[state]
     fn healing(event: &Event) -> Response<State> {
         match event {
-            Event::Jump { event_time } => {
+            Event::Jump { .. } => {
                 Transition(State::jumping())
             }
             Event::Heal => Transition(State::healing()),
@@ -124,7 +373,7 @@ impl PlayerStateMachine {
     #[state]
     fn crouching(event: &Event) -> Response<State> {
         match event {
-            Event::Jump { event_time } => {
+            Event::Jump { .. } => {
                 Transition(State::jumping())
             }
             Event::Heal => Transition(State::healing()),
@@ -136,7 +385,7 @@ impl PlayerStateMachine {
     #[state]
     fn falling(event: &Event) -> Response<State> {
         match event {
-            Event::Jump { event_time } => {
+            Event::Jump { .. } => {
                 Transition(State::jumping())
             }
             Event::Heal => Transition(State::healing()),
@@ -147,117 +396,147 @@ impl PlayerStateMachine {
     }
 }
 
+fn send_transitions(
+    mut players: Query<(Entity, &mut PlayerState)>,
+    mut transitions: EventWriter<StateTransition>,
+) {
+    for (entity, mut state_machine) in players.iter_mut() {
+        for (from, to) in
+            state_machine.0.transitions.drain(..)
+        {
+            transitions.send(StateTransition {
+                entity,
+                from,
+                to,
+            });
+        }
+    }
+}
+
 fn machine_events(
-    query_action_state: Query<
-        &ActionState<PlatformerAction>,
-    >,
-    mut commands: Commands,
     mut controllers: Query<(
+        Entity,
         &mut KinematicCharacterController,
         &KinematicCharacterControllerOutput,
         &Velocity,
         &mut PlayerState,
+        &SimulationInput,
     )>,
-    time: Res<Time>,
+    clock: Res<SimulationClock>,
+    mut landed: EventWriter<Landed>,
 ) {
-    for (_, _output, _, mut state_machine) in
+    for (_, _, _output, _, mut state_machine, _) in
         &mut controllers
+    {
+        if let State::Jumping {} = state_machine.0.state() {
+            if let Some(last_jump) =
+                state_machine.0.last_jump
+            {
+                if (clock.elapsed() - last_jump)
+                    > Duration::from_millis(500)
+                {
+                    state_machine.0.handle(&Event::Fall);
+                }
+            }
+        }
+    }
+    for (
+        entity,
+        _,
+        output,
+        _,
+        mut state_machine,
+        action_state,
+    ) in &mut controllers
     {
         match state_machine.0.state() {
-            // State::Idle {  } => todo!(),
+            State::Idle {} => {
+                // info!("idling");
+                if action_state.just_pressed(
+                    PlatformerAction::Jump,
+                ) {
+                    state_machine.0.handle(
+                        &Event::Jump {
+                            event_time: clock.elapsed(),
+                        },
+                    );
+                }
+            }
             State::Jumping {} => {
+                // info!("jumping");
                 if let Some(last_jump) =
                     state_machine.0.last_jump
                 {
-                    if (time.elapsed() - last_jump)
-                        > Duration::from_millis(500)
+                    if output.grounded
+                        && 
+                        // systems can run fast enough that the newly jumping
+                        // player can still be in their original pre-takeoff contact
+                        // with the ground
+                        //
+                        // maybe replace with "last_left_ground" field?
+                        clock.elapsed() - last_jump
+                            > Duration::from_millis(50)
                     {
                         state_machine
                             .0
-                            .handle(&Event::Fall);
+                            .handle(&Event::Land);
+                        landed.send(Landed {
+                            entity,
+                            fall_height: 0.,
+                        });
                     }
                 }
             }
-            // State::Crouching {  } => todo!(),
-            // State::Falling {  } => todo!(),
-            // State::Healing {  } => todo!(),
-            _ => {}
-        }
-    }
-    for action_state in &query_action_state {
-        for (_, output, _, mut state_machine) in
-            &mut controllers
-        {
-            match state_machine.0.state() {
-                State::Idle {} => {
-                    // info!("idling");
-                    if action_state.just_pressed(
-                        PlatformerAction::Jump,
-                    ) {
-                        state_machine.0.handle(
-                            &Event::Jump {
-                                event_time: time.elapsed(),
-                            },
-                        );
-                    }
-                }
-                State::Jumping {} => {
-                    // info!("jumping");
-                    if let Some(last_jump) =
-                        state_machine.0.last_jump
-                    {
-                        if output.grounded
-                            && 
-                            // systems can run fast enough that the newly jumping
-                            // player can still be in their original pre-takeoff contact
-                            // with the ground
-                            //
-                            // maybe replace with "last_left_ground" field?
-                            time.elapsed() - last_jump
-                                > Duration::from_millis(50)
-                        {
-                            state_machine
-                                .0
-                                .handle(&Event::Land);
-                        }
-                    }
-                }
-                State::Crouching {} => {
-                    // info!("crouching");
-                }
-                State::Healing {} => {
-                    // info!("healing");
-                }
-                State::Falling {} => {
-                    // info!("falling");
-                }
+            State::Crouching {} => {
+                // info!("crouching");
+            }
+            State::Healing {} => {
+                // info!("healing");
+            }
+            State::Falling {} => {
+                // info!("falling");
             }
         }
     }
 }
 fn fall(
-    mut commands: Commands,
     mut controllers: Query<(
+        Entity,
+        &Transform,
         &mut KinematicCharacterController,
         &KinematicCharacterControllerOutput,
         &Velocity,
         &mut PlayerState,
-        &ActionState<PlatformerAction>,
+        &SimulationInput,
     )>,
-    time: Res<Time>,
+    mut fall_starts: Local<HashMap<Entity, f32>>,
+    mut landed: EventWriter<Landed>,
 ) {
     for (
+        entity,
+        transform,
         mut controller,
         output,
-        velocity,
+        _,
         mut state_machine,
-        action_state,
+        _,
     ) in &mut controllers
     {
         if let State::Falling {} = state_machine.0.state() {
             if output.grounded {
                 state_machine.0.handle(&Event::Land);
+                let fall_start = fall_starts
+                    .remove(&entity)
+                    .unwrap_or(transform.translation.y);
+                landed.send(Landed {
+                    entity,
+                    fall_height: fall_start
+                        - transform.translation.y,
+                });
             } else {
+                fall_starts
+                    .entry(entity)
+                    .or_insert(transform.translation.y);
                 controller.translation =
                     match controller.translation {
                         Some(mut v) => {
@@ -271,20 +550,18 @@ fn fall(
     }
 }
 fn jump(
-    mut commands: Commands,
     mut controllers: Query<(
         &mut KinematicCharacterController,
         // &KinematicCharacterControllerOutput,
         &Velocity,
         &mut PlayerState,
-        &ActionState<PlatformerAction>,
+        &SimulationInput,
     )>,
-    time: Res<Time>,
 ) {
     for (
         mut controller,
         // output,
-        velocity,
+        _,
         mut state_machine,
         action_state,
     ) in &mut controllers
@@ -308,7 +585,8 @@ fn jump(
     }
 }
 
-const TargetTopSpeed: f32 = 300.0;
+/// How fast the player runs, in pixels a second
+const TARGET_TOP_SPEED: f32 = 300.0;
 // /// clamped_input is a 0.0-1.0 value
 // representing the user's /// desired percentage
 // of top speed to hold ///
@@ -324,38 +602,39 @@ const TargetTopSpeed: f32 = 300.0;
 //     let new_force = diff_to_make_up * 2.0;
 //     new_force
 // }
+/// How hard the player is pushing left (negative)
+/// or right (positive), from -1.0 to 1.0
+pub fn horizontal_input(
+    action_state: &ActionState<PlatformerAction>,
+) -> f32 {
+    if action_state.pressed(PlatformerAction::Horizontal)
+    {
+        action_state
+            .action_data(PlatformerAction::Horizontal)
+            .value
+    } else if action_state.pressed(PlatformerAction::Right)
+    {
+        action_state.clamped_value(PlatformerAction::Right)
+    } else if action_state.pressed(PlatformerAction::Left) {
+        -action_state.clamped_value(PlatformerAction::Left)
+    } else {
+        0.0
+    }
+}
+
 fn horizontal(
     mut controllers: Query<(
-        &ActionState<PlatformerAction>,
+        &SimulationInput,
         &mut KinematicCharacterController,
         &Velocity,
     )>,
-    time: Res<Time>,
 ) {
-    for (action_state, mut controller, velocity) in
+    for (action_state, mut controller, _) in
         controllers.iter_mut()
     {
-        let value = if action_state
-            .pressed(PlatformerAction::Horizontal)
-        {
-            action_state
-                .action_data(PlatformerAction::Horizontal)
-                .value
-        } else if action_state
-            .pressed(PlatformerAction::Right)
-        {
-            action_state
-                .clamped_value(PlatformerAction::Right)
-        } else if action_state
-            .pressed(PlatformerAction::Left)
-        {
-            -action_state
-                .clamped_value(PlatformerAction::Left)
-        } else {
-            0.0
-        };
-
-        let value = value * 300.0 * time.delta_seconds();
+        let value = horizontal_input(action_state)
+            * TARGET_TOP_SPEED
+            * TIMESTEP;
         controller.translation =
             match controller.translation {
                 Some(mut v) => {
@@ -367,13 +646,89 @@ fn horizontal(
     }
 }
 
+/// How long a dash lasts
+pub const DASH_TIME: Duration = Duration::from_millis(150);
+/// The shortest time from one dash starting to the
+/// next
+pub const DASH_COOLDOWN: Duration =
+    Duration::from_millis(800);
+/// In px/s
+const DASH_SPEED: f32 = 900.;
+
+/// A player's dash, and when they can dash again
+#[derive(Clone, Copy, Debug, Component)]
+pub struct Dash {
+    /// 1 for right, -1 for left
+    facing: f32,
+    until: Duration,
+    pub ready_at: Duration,
+}
+
+impl Default for Dash {
+    fn default() -> Self {
+        Self {
+            facing: 1.,
+            until: Duration::ZERO,
+            ready_at: Duration::ZERO,
+        }
+    }
+}
+
+impl Dash {
+    /// Whether the dash is still going at `now`
+    pub fn dashing(&self, now: Duration) -> bool {
+        now < self.until
+    }
+
+    /// How far through its cooldown the dash is at
+    /// `now`, from 0.0 just used to 1.0 ready
+    pub fn readiness(&self, now: Duration) -> f32 {
+        let left = self.ready_at.saturating_sub(now);
+        1. - left.as_secs_f32()
+            / DASH_COOLDOWN.as_secs_f32()
+    }
+}
+
+/// Shoots the player sideways, overriding the rest
+/// of their movement while it lasts
+fn dash(
+    clock: Res<SimulationClock>,
+    mut controllers: Query<(
+        &SimulationInput,
+        &mut KinematicCharacterController,
+        &mut Dash,
+    )>,
+) {
+    let now = clock.elapsed();
+    for (action_state, mut controller, mut dash) in
+        controllers.iter_mut()
+    {
+        let steering = horizontal_input(action_state);
+        if steering != 0. && now >= dash.until {
+            dash.facing = steering.signum();
+        }
+        if action_state.just_pressed(PlatformerAction::Dash)
+            && now >= dash.ready_at
+        {
+            dash.until = now + DASH_TIME;
+            dash.ready_at = now + DASH_COOLDOWN;
+        }
+        if now < dash.until {
+            controller.translation = Some(Vec2::new(
+                dash.facing * DASH_SPEED * TIMESTEP,
+                0.,
+            ));
+        }
+    }
+}
+
 fn debug_actions(
     query_action_state: Query<
         &ActionState<PlatformerAction>,
     >,
 ) {
     for action in query_action_state.iter() {
-        for aaction in action
+        for _action in action
             .get_pressed()
             .iter()
             .filter(|v| v != &&PlatformerAction::Horizontal)
@@ -0,0 +1,408 @@
+//! Where ground enemies can walk, and how they get
+//! from one platform to another.
+//!
+//! Every level with walls gets a [`NavGraph`] built
+//! from its collision IntGrid. A cell is walkable if
+//! it's empty with a wall right below it. Walkable
+//! cells are linked to their neighbours by walking,
+//! to lower ones by dropping off ledges, and to
+//! nearby ones by jumping.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{components::Wall, systems::walls_by_level};
+
+/// The highest jump, in cells, the graph links. Each
+/// enemy only uses the jumps it's able to make.
+const MAX_JUMP_CELLS: i32 = 6;
+/// The furthest a jump carries, in cells
+const JUMP_REACH: i32 = 4;
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(build_navigation);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LinkKind {
+    Walk,
+    /// Off a ledge, straight down
+    Drop,
+    Jump,
+}
+
+/// A way from one walkable cell to another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NavLink {
+    pub to: GridCoords,
+    pub kind: LinkKind,
+    cost: u32,
+}
+
+/// A level's walkable cells and the links between
+/// them. Lives on the level entity, so it's rebuilt
+/// along with the level's walls and goes away when
+/// the level unloads.
+#[derive(Clone, Debug, Default, Component)]
+pub struct NavGraph {
+    width: i32,
+    height: i32,
+    grid_size: i32,
+    walls: HashSet<GridCoords>,
+    links: HashMap<GridCoords, Vec<NavLink>>,
+}
+
+impl NavGraph {
+    pub fn new(
+        walls: HashSet<GridCoords>,
+        width: i32,
+        height: i32,
+        grid_size: i32,
+    ) -> Self {
+        let mut graph = NavGraph {
+            width,
+            height,
+            grid_size,
+            walls,
+            links: HashMap::new(),
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let cell = GridCoords { x, y };
+                if graph.standable(cell) {
+                    let links = graph.links_from(cell);
+                    graph.links.insert(cell, links);
+                }
+            }
+        }
+        graph
+    }
+
+    fn contains(&self, cell: GridCoords) -> bool {
+        (0..self.width).contains(&cell.x)
+            && (0..self.height).contains(&cell.y)
+    }
+
+    fn is_empty(&self, cell: GridCoords) -> bool {
+        self.contains(cell) && !self.walls.contains(&cell)
+    }
+
+    /// Empty, with a wall below
+    fn standable(&self, cell: GridCoords) -> bool {
+        self.is_empty(cell)
+            && self.walls.contains(&GridCoords {
+                x: cell.x,
+                y: cell.y - 1,
+            })
+    }
+
+    pub fn is_walkable(&self, cell: GridCoords) -> bool {
+        self.links.contains_key(&cell)
+    }
+
+    pub fn links(&self, cell: GridCoords) -> &[NavLink] {
+        self.links.get(&cell).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether every cell from `a` to `b` is empty,
+    /// going straight along a row or column
+    fn clear(&self, a: GridCoords, b: GridCoords) -> bool {
+        let (x0, x1) = (a.x.min(b.x), a.x.max(b.x));
+        let (y0, y1) = (a.y.min(b.y), a.y.max(b.y));
+        (x0..=x1).all(|x| {
+            (y0..=y1)
+                .all(|y| self.is_empty(GridCoords { x, y }))
+        })
+    }
+
+    fn links_from(&self, cell: GridCoords) -> Vec<NavLink> {
+        let mut links = Vec::new();
+        let link = |to: GridCoords, kind: LinkKind| {
+            let distance = (to.x - cell.x).abs()
+                + (to.y - cell.y).abs();
+            let cost = match kind {
+                LinkKind::Walk | LinkKind::Drop => distance,
+                // a little worse than walking the same
+                // distance, so enemies only jump when
+                // it helps
+                LinkKind::Jump => distance + 2,
+            };
+            NavLink {
+                to,
+                kind,
+                cost: cost as u32,
+            }
+        };
+
+        for side in [-1, 1] {
+            let next = GridCoords {
+                x: cell.x + side,
+                y: cell.y,
+            };
+            if self.standable(next) {
+                links.push(link(next, LinkKind::Walk));
+            } else if self.is_empty(next) {
+                // a ledge, fall until there's ground
+                let mut below = next;
+                while self.is_empty(below) {
+                    if self.standable(below) {
+                        links.push(link(
+                            below,
+                            LinkKind::Drop,
+                        ));
+                        break;
+                    }
+                    below.y -= 1;
+                }
+            }
+        }
+
+        for dy in -JUMP_REACH..=MAX_JUMP_CELLS {
+            for dx in -JUMP_REACH..=JUMP_REACH {
+                let to = GridCoords {
+                    x: cell.x + dx,
+                    y: cell.y + dy,
+                };
+                let gap = !self.standable(GridCoords {
+                    x: cell.x + dx.signum(),
+                    y: cell.y,
+                });
+                // walking or dropping already covers
+                // the rest
+                if dx == 0
+                    || (dy <= 0 && !gap)
+                    || (dy == 0 && dx.abs() < 2)
+                    || !self.standable(to)
+                {
+                    continue;
+                }
+                // up over the head of whichever end is
+                // higher, across, then down
+                let top = cell.y.max(to.y) + 1;
+                let over = |x| GridCoords { x, y: top };
+                if self.clear(cell, over(cell.x))
+                    && self.clear(over(cell.x), over(to.x))
+                    && self.clear(over(to.x), to)
+                {
+                    links.push(link(to, LinkKind::Jump));
+                }
+            }
+        }
+
+        links
+    }
+
+    /// The walkable cell at or below `cell`, where
+    /// something falling from there would land
+    pub fn ground_below(
+        &self,
+        mut cell: GridCoords,
+    ) -> Option<GridCoords> {
+        while self.is_empty(cell) {
+            if self.is_walkable(cell) {
+                return Some(cell);
+            }
+            cell.y -= 1;
+        }
+        None
+    }
+
+    /// The cheapest way from one walkable cell to
+    /// another, using only jumps up to `jump_cells`
+    /// high. Doesn't include `from`.
+    pub fn find_path(
+        &self,
+        from: GridCoords,
+        to: GridCoords,
+        jump_cells: i32,
+    ) -> Option<Vec<NavLink>> {
+        if !self.is_walkable(from) || !self.is_walkable(to)
+        {
+            return None;
+        }
+        // manhattan distance never overestimates, as
+        // no link costs less than the distance it
+        // covers
+        let estimate = |cell: GridCoords| {
+            ((cell.x - to.x).abs() + (cell.y - to.y).abs())
+                as u32
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut costs = HashMap::from([(from, 0)]);
+        let mut came_from: HashMap<
+            GridCoords,
+            (GridCoords, NavLink),
+        > = HashMap::new();
+        open.push(Reverse((
+            estimate(from),
+            from.x,
+            from.y,
+        )));
+
+        while let Some(Reverse((_, x, y))) = open.pop() {
+            let cell = GridCoords { x, y };
+            if cell == to {
+                let mut path = Vec::new();
+                let mut at = to;
+                while let Some((previous, link)) =
+                    came_from.get(&at)
+                {
+                    path.push(*link);
+                    at = *previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let cost = costs[&cell];
+            for link in self.links(cell) {
+                let rise = (link.to.y - cell.y).max(1);
+                if link.kind == LinkKind::Jump
+                    && rise > jump_cells
+                {
+                    continue;
+                }
+                let next_cost = cost + link.cost;
+                if costs
+                    .get(&link.to)
+                    .is_none_or(|known| next_cost < *known)
+                {
+                    costs.insert(link.to, next_cost);
+                    came_from
+                        .insert(link.to, (cell, *link));
+                    open.push(Reverse((
+                        next_cost + estimate(link.to),
+                        link.to.x,
+                        link.to.y,
+                    )));
+                }
+            }
+        }
+        None
+    }
+
+    /// The cell at a position relative to the level
+    pub fn cell_at(&self, position: Vec2) -> GridCoords {
+        let cell =
+            (position / self.grid_size as f32).floor();
+        GridCoords {
+            x: cell.x as i32,
+            y: cell.y as i32,
+        }
+    }
+
+    /// The center of a cell, relative to the level
+    pub fn cell_center(&self, cell: GridCoords) -> Vec2 {
+        (Vec2::new(cell.x as f32, cell.y as f32) + 0.5)
+            * self.grid_size as f32
+    }
+}
+
+/// A step along a path, in world space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Waypoint {
+    pub position: Vec2,
+    /// How to get here from the previous waypoint
+    pub kind: LinkKind,
+}
+
+/// Finds paths through whichever level they start in
+#[derive(SystemParam)]
+pub struct Navigation<'w, 's> {
+    graphs: Query<
+        'w,
+        's,
+        (&'static NavGraph, &'static GlobalTransform),
+    >,
+}
+
+impl Navigation<'_, '_> {
+    /// A path between two world positions, both
+    /// dropped onto the ground below them, for
+    /// something that can jump `jump_height` world
+    /// pixels
+    pub fn find_path(
+        &self,
+        from: Vec2,
+        to: Vec2,
+        jump_height: f32,
+    ) -> Option<Vec<Waypoint>> {
+        let (graph, origin) = self.graphs.iter().find_map(
+            |(graph, level)| {
+                let origin = level.translation().truncate();
+                graph
+                    .contains(graph.cell_at(from - origin))
+                    .then_some((graph, origin))
+            },
+        )?;
+        let start = graph
+            .ground_below(graph.cell_at(from - origin))?;
+        let goal = graph
+            .ground_below(graph.cell_at(to - origin))?;
+        let jump_cells =
+            (jump_height / graph.grid_size as f32) as i32;
+        let path =
+            graph.find_path(start, goal, jump_cells)?;
+        Some(
+            path.iter()
+                .map(|link| Waypoint {
+                    position: origin
+                        + graph.cell_center(link.to),
+                    kind: link.kind,
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Builds a level's [`NavGraph`] whenever its walls
+/// spawn, alongside `spawn_wall_collision`
+fn build_navigation(
+    mut commands: Commands,
+    wall_query: Query<(&GridCoords, &Parent), Added<Wall>>,
+    parent_query: Query<&Parent, Without<Wall>>,
+    level_query: Query<&Handle<LdtkLevel>>,
+    levels: Res<Assets<LdtkLevel>>,
+) {
+    if wall_query.is_empty() {
+        return;
+    }
+    for (level_entity, walls) in
+        walls_by_level(&wall_query, &parent_query)
+    {
+        let level = match level_query
+            .get(level_entity)
+            .ok()
+            .and_then(|handle| levels.get(handle))
+        {
+            Some(level) => level,
+            None => continue,
+        };
+        let layer = match level
+            .level
+            .layer_instances
+            .as_ref()
+            .and_then(|layers| layers.first())
+        {
+            Some(layer) => layer,
+            None => continue,
+        };
+        commands.entity(level_entity).insert(
+            NavGraph::new(
+                walls,
+                layer.c_wid,
+                layer.c_hei,
+                layer.grid_size,
+            ),
+        );
+    }
+}
@@ -0,0 +1,664 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{
+        AssetLoader, AssetPath, LoadContext, LoadedAsset,
+    },
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    checkpoint::CheckpointReached,
+    combat::{DamageTaken, Killed},
+    components::Player,
+    movement::{
+        Dash, Landed, PlayerState, SimulationClock, State,
+    },
+    GameState,
+};
+
+/// Every particle effect, by name
+pub const PARTICLE_PRESETS: &str = "effects.particles.json";
+
+/// Past this many particles alive at once, new ones
+/// are dropped
+pub const MAX_PARTICLES: usize = 1024;
+
+/// Where the player's feet are, from their center
+const FEET: f32 = -24.;
+
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ParticlePresets>()
+            .init_asset_loader::<ParticlePresetsLoader>()
+            .init_resource::<ParticlePool>()
+            .init_resource::<ParticleRng>()
+            .add_event::<SpawnParticles>()
+            .add_startup_system(load_particle_presets)
+            .add_system(add_player_emitters)
+            .add_system(
+                player_effects
+                    .run_in_state(GameState::Playing)
+                    .label(ParticleTriggers),
+            )
+            .add_system(
+                landing_dust
+                    .run_in_state(GameState::Playing)
+                    .label(ParticleTriggers),
+            )
+            .add_system(
+                checkpoint_bursts
+                    .run_in_state(GameState::Playing)
+                    .label(ParticleTriggers),
+            )
+            .add_system(
+                damage_bursts
+                    .run_in_state(GameState::Playing)
+                    .label(ParticleTriggers),
+            )
+            .add_system(
+                run_emitters
+                    .label(ParticleSpawning)
+                    .after(ParticleTriggers),
+            )
+            .add_system(
+                spawn_bursts
+                    .label(ParticleSpawning)
+                    .after(ParticleTriggers),
+            )
+            .add_system(
+                update_particles.after(ParticleSpawning),
+            )
+            .add_enter_system(
+                GameState::MainMenu,
+                clear_particles,
+            );
+    }
+}
+
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+struct ParticleTriggers;
+
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+struct ParticleSpawning;
+
+/// How one kind of particle looks and moves. Pairs
+/// of numbers are ranges picked from at random, or
+/// start and end values over a particle's life.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ParticlePreset {
+    /// Relative to the presets file, one is picked
+    /// for each particle
+    pub textures: Vec<String>,
+    #[serde(skip)]
+    pub images: Vec<Handle<Image>>,
+    /// Particles per second from an emitter
+    pub rate: f32,
+    /// Particles in a one-off burst
+    pub count: usize,
+    /// In seconds
+    pub lifetime: [f32; 2],
+    /// In world pixels per second
+    pub speed: [f32; 2],
+    /// The direction particles head off in, in
+    /// degrees counterclockwise from the right
+    pub angle: f32,
+    /// How far either side of `angle` they can go
+    pub spread: f32,
+    pub gravity: [f32; 2],
+    /// Start and end size, in world pixels
+    pub size: [f32; 2],
+    /// Start and end color, as RGBA
+    pub color: [[f32; 4]; 2],
+    /// In radians per second
+    pub spin: [f32; 2],
+}
+
+impl Default for ParticlePreset {
+    fn default() -> Self {
+        Self {
+            textures: vec![],
+            images: vec![],
+            rate: 10.,
+            count: 8,
+            lifetime: [0.3, 0.6],
+            speed: [20., 60.],
+            angle: 90.,
+            spread: 180.,
+            gravity: [0., 0.],
+            size: [8., 2.],
+            color: [[1., 1., 1., 1.], [1., 1., 1., 0.]],
+            spin: [0., 0.],
+        }
+    }
+}
+
+#[derive(Debug, TypeUuid)]
+#[uuid = "b714a60f-7a14-4304-bf38-b4bc30d931df"]
+pub struct ParticlePresets(
+    pub HashMap<String, ParticlePreset>,
+);
+
+#[derive(Default)]
+pub struct ParticlePresetsLoader;
+
+impl AssetLoader for ParticlePresetsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>>
+    {
+        Box::pin(async move {
+            let mut presets: HashMap<
+                String,
+                ParticlePreset,
+            > = serde_json::from_slice(bytes)?;
+            let dir = load_context
+                .path()
+                .parent()
+                .map_or_else(Default::default, |dir| {
+                    dir.to_path_buf()
+                });
+            let mut dependencies = vec![];
+            for preset in presets.values_mut() {
+                for texture in preset.textures.iter() {
+                    let path =
+                        AssetPath::from(dir.join(texture));
+                    preset.images.push(
+                        load_context
+                            .get_handle(path.clone()),
+                    );
+                    dependencies.push(path);
+                }
+            }
+            load_context.set_default_asset(
+                LoadedAsset::new(ParticlePresets(presets))
+                    .with_dependencies(dependencies),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["particles.json"]
+    }
+}
+
+#[derive(Debug, Resource)]
+struct ParticlePresetsHandle(Handle<ParticlePresets>);
+
+fn load_particle_presets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(ParticlePresetsHandle(
+        asset_server.load(PARTICLE_PRESETS),
+    ));
+}
+
+/// A one-off burst of a preset's `count` particles
+#[derive(Clone, Debug)]
+pub struct SpawnParticles {
+    pub preset: String,
+    pub position: Vec2,
+    /// Mirrors the preset's direction
+    pub flip_x: bool,
+}
+
+/// Emits a preset's `rate` particles per second from
+/// wherever it is while `active`
+#[derive(Clone, Debug, Default, Component)]
+pub struct ParticleEmitter {
+    pub preset: String,
+    pub active: bool,
+    /// Mirrors the preset's direction
+    pub flip_x: bool,
+    owed: f32,
+}
+
+impl ParticleEmitter {
+    pub fn new(preset: &str) -> Self {
+        Self {
+            preset: preset.to_string(),
+            ..default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Component)]
+struct Particle {
+    alive: bool,
+    age: f32,
+    lifetime: f32,
+    velocity: Vec2,
+    gravity: Vec2,
+    spin: f32,
+    size: [f32; 2],
+    color: [Vec4; 2],
+}
+
+/// Dead particles, kept hidden to be reused instead
+/// of despawned
+#[derive(Debug, Default, Resource)]
+pub struct ParticlePool {
+    free: Vec<Entity>,
+    /// Every particle entity, dead or alive
+    total: usize,
+}
+
+/// Cheap xorshift noise, particles don't need
+/// anything better
+#[derive(Debug, Resource)]
+struct ParticleRng(u32);
+
+impl Default for ParticleRng {
+    fn default() -> Self {
+        Self(0x9E37_79B9)
+    }
+}
+
+impl ParticleRng {
+    /// Somewhere in `low..high`
+    fn range(&mut self, [low, high]: [f32; 2]) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        low + (high - low)
+            * (self.0 as f32 / u32::MAX as f32)
+    }
+}
+
+fn spawn_particle(
+    commands: &mut Commands,
+    pool: &mut ParticlePool,
+    rng: &mut ParticleRng,
+    preset: &ParticlePreset,
+    position: Vec2,
+    flip_x: bool,
+) {
+    let entity = match pool.free.pop() {
+        Some(entity) => entity,
+        None if pool.total < MAX_PARTICLES => {
+            pool.total += 1;
+            commands.spawn_empty().id()
+        }
+        None => return,
+    };
+
+    let mut angle = rng
+        .range([
+            preset.angle - preset.spread,
+            preset.angle + preset.spread,
+        ])
+        .to_radians();
+    if flip_x {
+        angle = std::f32::consts::PI - angle;
+    }
+    let image = match preset.images.len() {
+        0 => default(),
+        len => preset.images[(rng.range([0., len as f32])
+            as usize)
+            .min(len - 1)]
+        .clone(),
+    };
+    let [start, end] = preset.color;
+    let color = [Vec4::from(start), Vec4::from(end)];
+
+    commands.entity(entity).insert((
+        Particle {
+            alive: true,
+            age: 0.,
+            lifetime: rng.range(preset.lifetime),
+            velocity: Vec2::from_angle(angle)
+                * rng.range(preset.speed),
+            gravity: Vec2::from(preset.gravity),
+            spin: rng.range(preset.spin),
+            size: preset.size,
+            color,
+        },
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::from(color[0]),
+                custom_size: Some(Vec2::splat(
+                    preset.size[0],
+                )),
+                ..default()
+            },
+            texture: image,
+            // in front of the level and players
+            transform: Transform::from_translation(
+                position.extend(50.),
+            ),
+            ..default()
+        },
+    ));
+}
+
+fn spawn_bursts(
+    mut commands: Commands,
+    mut pool: ResMut<ParticlePool>,
+    mut rng: ResMut<ParticleRng>,
+    presets: Res<Assets<ParticlePresets>>,
+    handle: Option<Res<ParticlePresetsHandle>>,
+    mut bursts: EventReader<SpawnParticles>,
+) {
+    let presets = match handle
+        .and_then(|handle| presets.get(&handle.0))
+    {
+        Some(presets) => presets,
+        None => return,
+    };
+    for burst in bursts.iter() {
+        let preset = match presets.0.get(&burst.preset) {
+            Some(preset) => preset,
+            None => {
+                warn!(
+                    "no particle preset `{}`",
+                    burst.preset
+                );
+                continue;
+            }
+        };
+        for _ in 0..preset.count {
+            spawn_particle(
+                &mut commands,
+                &mut pool,
+                &mut rng,
+                preset,
+                burst.position,
+                burst.flip_x,
+            );
+        }
+    }
+}
+
+fn run_emitters(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pool: ResMut<ParticlePool>,
+    mut rng: ResMut<ParticleRng>,
+    presets: Res<Assets<ParticlePresets>>,
+    handle: Option<Res<ParticlePresetsHandle>>,
+    mut emitters: Query<(
+        &mut ParticleEmitter,
+        &GlobalTransform,
+    )>,
+) {
+    let presets = match handle
+        .and_then(|handle| presets.get(&handle.0))
+    {
+        Some(presets) => presets,
+        None => return,
+    };
+    for (mut emitter, transform) in emitters.iter_mut() {
+        if !emitter.active {
+            emitter.owed = 0.;
+            continue;
+        }
+        let preset = match presets.0.get(&emitter.preset) {
+            Some(preset) => preset,
+            None => continue,
+        };
+        emitter.owed += preset.rate * time.delta_seconds();
+        while emitter.owed >= 1. {
+            emitter.owed -= 1.;
+            spawn_particle(
+                &mut commands,
+                &mut pool,
+                &mut rng,
+                preset,
+                transform.translation().truncate(),
+                emitter.flip_x,
+            );
+        }
+    }
+}
+
+fn update_particles(
+    time: Res<Time>,
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &mut Sprite,
+        &mut Visibility,
+    )>,
+) {
+    let dt = time.delta_seconds();
+    for (
+        entity,
+        mut particle,
+        mut transform,
+        mut sprite,
+        mut visibility,
+    ) in particles.iter_mut()
+    {
+        if !particle.alive {
+            continue;
+        }
+        particle.age += dt;
+        if particle.age >= particle.lifetime {
+            particle.alive = false;
+            visibility.is_visible = false;
+            pool.free.push(entity);
+            continue;
+        }
+
+        let gravity = particle.gravity;
+        particle.velocity += gravity * dt;
+        transform.translation +=
+            (particle.velocity * dt).extend(0.);
+        transform.rotate_z(particle.spin * dt);
+
+        let t = particle.age / particle.lifetime;
+        let [start, end] = particle.size;
+        sprite.custom_size =
+            Some(Vec2::splat(start + (end - start) * t));
+        let [start, end] = particle.color;
+        sprite.color = Color::from(start.lerp(end, t));
+    }
+}
+
+/// Puts every live particle back in the pool, so
+/// none are left over the menus
+fn clear_particles(
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Visibility,
+    )>,
+) {
+    for (entity, mut particle, mut visibility) in
+        particles.iter_mut()
+    {
+        if particle.alive {
+            particle.alive = false;
+            visibility.is_visible = false;
+            pool.free.push(entity);
+        }
+    }
+}
+
+/// The continuous effects every player has
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+enum PlayerEffect {
+    RunDust,
+    DashTrail,
+    WallSparks,
+}
+
+fn add_player_emitters(
+    mut commands: Commands,
+    players: Query<Entity, Added<Player>>,
+) {
+    for player in players.iter() {
+        commands.entity(player).with_children(|parent| {
+            for (effect, preset, y) in [
+                (PlayerEffect::RunDust, "run_dust", FEET),
+                (PlayerEffect::DashTrail, "dash_trail", 0.),
+                (
+                    PlayerEffect::WallSparks,
+                    "wall_sparks",
+                    0.,
+                ),
+            ] {
+                parent.spawn((
+                    ParticleEmitter::new(preset),
+                    effect,
+                    TransformBundle::from(
+                        Transform::from_xyz(0., y, 0.),
+                    ),
+                ));
+            }
+        });
+    }
+}
+
+/// Turns each player's emitters on and off to
+/// match what they're doing
+fn player_effects(
+    clock: Res<SimulationClock>,
+    players: Query<
+        (
+            &PlayerState,
+            &Dash,
+            &KinematicCharacterControllerOutput,
+        ),
+        With<Player>,
+    >,
+    mut emitters: Query<(
+        &Parent,
+        &PlayerEffect,
+        &mut ParticleEmitter,
+        &mut Transform,
+    )>,
+) {
+    for (parent, effect, mut emitter, mut transform) in
+        emitters.iter_mut()
+    {
+        let (state, dash, output) =
+            match players.get(parent.get()) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+        let moving = output.effective_translation.x;
+        match effect {
+            PlayerEffect::RunDust => {
+                emitter.active =
+                    output.grounded && moving.abs() > 0.1;
+                // kicked up behind the player
+                emitter.flip_x = moving < 0.;
+            }
+            PlayerEffect::DashTrail => {
+                emitter.active =
+                    dash.dashing(clock.elapsed());
+            }
+            PlayerEffect::WallSparks => {
+                let wall = output.collisions.iter().any(
+                    |collision| {
+                        collision.toi.normal1.x.abs() > 0.7
+                    },
+                );
+                let side =
+                    output.desired_translation.x.signum();
+                emitter.active = wall
+                    && !output.grounded
+                    && matches!(
+                        state.state(),
+                        State::Falling {}
+                    );
+                // the preset sprays away from a wall on
+                // the right
+                emitter.flip_x = side < 0.;
+                transform.translation.x = side * 12.;
+            }
+        }
+    }
+}
+
+fn landing_dust(
+    mut landings: EventReader<Landed>,
+    players: Query<&GlobalTransform>,
+    mut bursts: EventWriter<SpawnParticles>,
+) {
+    for landed in landings.iter() {
+        // stepping off a curb isn't worth a cloud
+        if landed.fall_height < 8. {
+            continue;
+        }
+        if let Ok(transform) = players.get(landed.entity) {
+            let feet = transform.translation().truncate()
+                + Vec2::new(0., FEET);
+            for flip_x in [false, true] {
+                bursts.send(SpawnParticles {
+                    preset: "land_dust".to_string(),
+                    position: feet,
+                    flip_x,
+                });
+            }
+        }
+    }
+}
+
+fn checkpoint_bursts(
+    mut reached: EventReader<CheckpointReached>,
+    checkpoints: Query<&GlobalTransform>,
+    mut bursts: EventWriter<SpawnParticles>,
+) {
+    for event in reached.iter() {
+        if let Ok(transform) =
+            checkpoints.get(event.checkpoint)
+        {
+            bursts.send(SpawnParticles {
+                preset: "checkpoint".to_string(),
+                position: transform
+                    .translation()
+                    .truncate(),
+                flip_x: false,
+            });
+        }
+    }
+}
+
+/// Sparks where something gets hurt, and a puff of
+/// smoke where it dies
+fn damage_bursts(
+    mut damage: EventReader<DamageTaken>,
+    mut killed: EventReader<Killed>,
+    targets: Query<&GlobalTransform>,
+    mut bursts: EventWriter<SpawnParticles>,
+) {
+    for damage in damage.iter() {
+        if let Ok(transform) = targets.get(damage.target) {
+            bursts.send(SpawnParticles {
+                preset: "hit".to_string(),
+                position: transform
+                    .translation()
+                    .truncate(),
+                flip_x: false,
+            });
+        }
+    }
+    for killed in killed.iter() {
+        bursts.send(SpawnParticles {
+            preset: "poof".to_string(),
+            position: killed.position,
+            flip_x: false,
+        });
+    }
+}
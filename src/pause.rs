@@ -0,0 +1,229 @@
+use bevy::{app::AppExit, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    actions::PlatformerAction,
+    camera::{
+        gameplay_frozen, LevelTransition,
+        LevelTransitionSettings,
+    },
+    combat::HitStop,
+    components::Player,
+    menu::{
+        spawn_menu, MenuBack, MenuConfirmed, MenuSystem,
+    },
+    settings::spawn_settings_menu,
+    systems::respawn_levels,
+    GameState,
+};
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            pause_game.run_in_state(GameState::Playing),
+        )
+        .add_enter_system(
+            GameState::Paused,
+            freeze_gameplay,
+        )
+        .add_enter_system(
+            GameState::Paused,
+            spawn_pause_menu,
+        )
+        .add_exit_system(GameState::Paused, thaw_gameplay)
+        .add_exit_system(
+            GameState::Paused,
+            despawn_pause_menu,
+        )
+        .add_system(
+            swallow_player_input
+                .run_in_state(GameState::Paused),
+        )
+        .add_system(
+            pause_menu_actions
+                .run_in_state(GameState::Paused)
+                .after(MenuSystem::Navigate),
+        )
+        .init_resource::<PauseScreen>();
+    }
+}
+
+/// What is shown while [`GameState::Paused`]
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Resource,
+)]
+pub enum PauseScreen {
+    #[default]
+    Menu,
+    /// The inventory of the given player
+    Inventory(Entity),
+}
+
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct PauseMenu;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+enum PauseItem {
+    Resume,
+    Restart,
+    Settings,
+    MainMenu,
+    Quit,
+}
+
+/// Any player can pause
+fn pause_game(
+    mut commands: Commands,
+    players: Query<
+        &ActionState<PlatformerAction>,
+        With<Player>,
+    >,
+) {
+    if players.iter().any(|action_state| {
+        action_state.just_pressed(PlatformerAction::Pause)
+    }) {
+        commands.insert_resource(PauseScreen::Menu);
+        commands
+            .insert_resource(NextState(GameState::Paused));
+    }
+}
+
+/// Stops `Time`, so timers, animations and the
+/// state machine's jump timing all hold still, and
+/// stops rapier along with any in-flight character
+/// controller movement
+pub fn freeze_gameplay(
+    mut time: ResMut<Time>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut controllers: Query<
+        &mut KinematicCharacterController,
+        With<Player>,
+    >,
+) {
+    time.pause();
+    rapier_config.physics_pipeline_active = false;
+    for mut controller in controllers.iter_mut() {
+        controller.translation = None;
+    }
+}
+
+pub fn thaw_gameplay(
+    mut time: ResMut<Time>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    transition: Option<Res<LevelTransition>>,
+    settings: Option<Res<LevelTransitionSettings>>,
+    hit_stop: Option<Res<HitStop>>,
+) {
+    time.unpause();
+    // a level transition keeps physics stopped
+    // until it's done
+    rapier_config.physics_pipeline_active =
+        !gameplay_frozen(transition, settings, hit_stop);
+}
+
+/// Consumes every player action while paused, so
+/// presses made in the menu are released by the
+/// time gameplay resumes and stay released until the
+/// buttons are let go
+fn swallow_player_input(
+    mut players: Query<
+        &mut ActionState<PlatformerAction>,
+        With<Player>,
+    >,
+) {
+    for mut action_state in players.iter_mut() {
+        for action in PlatformerAction::variants() {
+            if action_state.pressed(action) {
+                action_state.consume(action);
+            }
+        }
+    }
+}
+
+fn spawn_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    screen: Res<PauseScreen>,
+) {
+    if *screen != PauseScreen::Menu {
+        return;
+    }
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        "Paused",
+        1,
+        PauseMenu,
+        |menu| {
+            menu.item("Resume", PauseItem::Resume);
+            menu.item("Restart level", PauseItem::Restart);
+            menu.item("Settings", PauseItem::Settings);
+            menu.item("Main menu", PauseItem::MainMenu);
+            menu.item("Quit", PauseItem::Quit);
+        },
+    );
+}
+
+fn despawn_pause_menu(
+    mut commands: Commands,
+    menus: Query<Entity, With<PauseMenu>>,
+) {
+    for menu in menus.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
+
+fn pause_menu_actions(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut confirmed: EventReader<MenuConfirmed>,
+    mut back: EventReader<MenuBack>,
+    items: Query<&PauseItem>,
+    menus: Query<(), With<PauseMenu>>,
+    levels: Query<Entity, With<Handle<LdtkLevel>>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for MenuConfirmed(item) in confirmed.iter() {
+        match items.get(*item) {
+            Ok(PauseItem::Resume) => {
+                commands.insert_resource(NextState(
+                    GameState::Playing,
+                ));
+            }
+            Ok(PauseItem::Restart) => {
+                respawn_levels(&mut commands, &levels);
+                commands.insert_resource(NextState(
+                    GameState::Playing,
+                ));
+            }
+            Ok(PauseItem::Settings) => {
+                spawn_settings_menu(
+                    &mut commands,
+                    &asset_server,
+                    2,
+                );
+            }
+            Ok(PauseItem::MainMenu) => {
+                commands.insert_resource(NextState(
+                    GameState::MainMenu,
+                ));
+            }
+            Ok(PauseItem::Quit) => exit.send(AppExit),
+            Err(_) => {}
+        }
+    }
+    for MenuBack(menu) in back.iter() {
+        if menus.contains(*menu) {
+            commands.insert_resource(NextState(
+                GameState::Playing,
+            ));
+        }
+    }
+}
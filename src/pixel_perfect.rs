@@ -0,0 +1,298 @@
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    math::Rect,
+    prelude::*,
+    render::{
+        camera::{RenderTarget, Viewport},
+        render_resource::{
+            Extent3d, TextureDescriptor, TextureDimension,
+            TextureFormat, TextureUsages,
+        },
+        texture::ImageSampler,
+        view::RenderLayers,
+    },
+    window::WindowId,
+};
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::camera::MainCamera;
+
+pub struct PixelPerfectPlugin;
+
+impl Plugin for PixelPerfectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PixelPerfectSettings>()
+            .init_resource::<SubpixelOffset>()
+            .add_system(update_render_target)
+            .add_system(
+                retarget_main_camera
+                    .after(update_render_target),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                upscale_render_target,
+            );
+    }
+}
+
+/// Opt-in pixel-perfect rendering.
+///
+/// When enabled, the [`MainCamera`] renders to a
+/// low-resolution image that is exactly one texel
+/// per world pixel, which is then drawn to the
+/// window at the largest integer scale that fits.
+#[derive(Clone, Debug, Resource)]
+pub struct PixelPerfectSettings {
+    pub enabled: bool,
+    /// How many LDtk grid cells the low-resolution
+    /// target shows
+    pub tiles: UVec2,
+    /// Snap the camera to whole pixels but shift
+    /// the upscaled image by the remainder, so
+    /// scrolling isn't limited to one screen pixel
+    /// per world pixel
+    pub smooth_scrolling: bool,
+}
+
+impl Default for PixelPerfectSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tiles: UVec2::new(20, 11),
+            smooth_scrolling: true,
+        }
+    }
+}
+
+/// The low-resolution image the [`MainCamera`]
+/// renders to while pixel-perfect mode is enabled
+#[derive(Debug, Resource)]
+pub struct PixelPerfectTarget {
+    pub image: Handle<Image>,
+    /// The part of the world shown, in world pixels
+    pub size: UVec2,
+    /// Extra pixels rendered on every side of the
+    /// view for smooth scrolling
+    pub margin: u32,
+}
+
+/// How far the camera is from the whole pixel it
+/// was snapped to, in world pixels
+#[derive(Debug, Default, Resource)]
+pub struct SubpixelOffset(pub Vec2);
+
+/// Draws the upscaled [`PixelPerfectTarget`]
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct UpscaleCamera;
+
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct UpscaledImage;
+
+const UPSCALE_LAYER: u8 = 1;
+
+/// Snaps a camera rect to whole world pixels.
+///
+/// Returns the `size` rect the camera should
+/// render, grown by `margin` pixels on every side,
+/// and the remainder that was snapped away.
+pub fn snap_to_pixels(
+    rect: Rect,
+    size: UVec2,
+    margin: u32,
+) -> (Rect, Vec2) {
+    let snapped = rect.min.round();
+    let margin = Vec2::splat(margin as f32);
+    let min = snapped - margin;
+    let max = snapped + size.as_vec2() + margin;
+
+    (Rect { min, max }, rect.min - snapped)
+}
+
+/// Creates or removes the low-resolution target
+/// when the settings or the LDtk grid size change
+fn update_render_target(
+    mut commands: Commands,
+    settings: Res<PixelPerfectSettings>,
+    target: Option<Res<PixelPerfectTarget>>,
+    mut images: ResMut<Assets<Image>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    worlds: Query<&Handle<LdtkAsset>>,
+    upscaled: Query<
+        Entity,
+        Or<(With<UpscaleCamera>, With<UpscaledImage>)>,
+    >,
+    mut subpixel: ResMut<SubpixelOffset>,
+) {
+    let grid_size = worlds
+        .iter()
+        .find_map(|handle| ldtk_assets.get(handle))
+        .map(|ldtk| ldtk.project.default_grid_size);
+
+    let wanted = match grid_size {
+        Some(grid_size) if settings.enabled => {
+            let margin = if settings.smooth_scrolling {
+                1
+            } else {
+                0
+            };
+            Some((
+                settings.tiles * grid_size as u32,
+                margin,
+            ))
+        }
+        _ => None,
+    };
+    let current = target
+        .as_ref()
+        .map(|target| (target.size, target.margin));
+    if wanted == current {
+        return;
+    }
+
+    for entity in upscaled.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    subpixel.0 = Vec2::ZERO;
+
+    if let Some((size, margin)) = wanted {
+        let extent = Extent3d {
+            width: size.x + margin * 2,
+            height: size.y + margin * 2,
+            ..default()
+        };
+        let mut image = Image {
+            texture_descriptor: TextureDescriptor {
+                label: Some("pixel_perfect_target"),
+                size: extent,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Bgra8UnormSrgb,
+                mip_level_count: 1,
+                sample_count: 1,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_DST
+                    | TextureUsages::RENDER_ATTACHMENT,
+            },
+            sampler_descriptor: ImageSampler::nearest(),
+            ..default()
+        };
+        image.resize(extent);
+        let image = images.add(image);
+
+        commands.spawn((
+            Camera2dBundle {
+                camera: Camera {
+                    priority: 1,
+                    ..default()
+                },
+                camera_2d: Camera2d {
+                    clear_color: ClearColorConfig::None,
+                },
+                ..default()
+            },
+            RenderLayers::layer(UPSCALE_LAYER),
+            UiCameraConfig { show_ui: false },
+            UpscaleCamera,
+        ));
+        commands.spawn((
+            SpriteBundle {
+                texture: image.clone(),
+                ..default()
+            },
+            RenderLayers::layer(UPSCALE_LAYER),
+            UpscaledImage,
+        ));
+
+        commands.insert_resource(PixelPerfectTarget {
+            image,
+            size,
+            margin,
+        });
+    } else {
+        commands.remove_resource::<PixelPerfectTarget>();
+    }
+}
+
+/// Points the [`MainCamera`] at the low-resolution
+/// target while there is one, and back at the
+/// window otherwise
+fn retarget_main_camera(
+    target: Option<Res<PixelPerfectTarget>>,
+    mut cameras: Query<
+        (&mut Camera, &mut Camera2d),
+        With<MainCamera>,
+    >,
+) {
+    let (render_target, clear_color) = match target {
+        // nothing else clears the low-resolution image
+        Some(target) => (
+            RenderTarget::Image(target.image.clone()),
+            ClearColorConfig::Default,
+        ),
+        None => (
+            RenderTarget::Window(WindowId::primary()),
+            ClearColorConfig::None,
+        ),
+    };
+    for (mut camera, mut camera_2d) in cameras.iter_mut() {
+        if camera.target != render_target {
+            camera.target = render_target.clone();
+            camera_2d.clear_color = clear_color.clone();
+        }
+    }
+}
+
+/// Scales the low-resolution image by the largest
+/// integer factor that fits the window, and crops
+/// the smooth scrolling margin with the upscale
+/// camera's viewport
+fn upscale_render_target(
+    windows: Res<Windows>,
+    target: Option<Res<PixelPerfectTarget>>,
+    subpixel: Res<SubpixelOffset>,
+    mut cameras: Query<&mut Camera, With<UpscaleCamera>>,
+    mut sprites: Query<&mut Transform, With<UpscaledImage>>,
+) {
+    let (target, window) =
+        match (target, windows.get_primary()) {
+            (Some(target), Some(window)) => {
+                (target, window)
+            }
+            _ => return,
+        };
+    let window_size = UVec2::new(
+        window.physical_width(),
+        window.physical_height(),
+    );
+    if window_size.x == 0 || window_size.y == 0 {
+        return;
+    }
+
+    let factor =
+        (window_size / target.size).min_element().max(1);
+    let viewport_size =
+        (target.size * factor).min(window_size);
+
+    for mut camera in cameras.iter_mut() {
+        camera.viewport = Some(Viewport {
+            physical_position: (window_size
+                - viewport_size)
+                / 2,
+            physical_size: viewport_size,
+            ..default()
+        });
+    }
+
+    // the sprite is positioned in logical pixels,
+    // but scaled in physical ones
+    let scale =
+        factor as f32 / window.scale_factor() as f32;
+    for mut transform in sprites.iter_mut() {
+        transform.scale = Vec3::new(scale, scale, 1.);
+        transform.translation =
+            (-subpixel.0 * scale).extend(0.);
+    }
+}
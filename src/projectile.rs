@@ -0,0 +1,388 @@
+use std::{collections::HashSet, time::Duration};
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    camera::gameplay_frozen,
+    combat::{DamageKind, DealDamage},
+    components::{Health, Player, Turret},
+    enemy::line_of_sight,
+    movement::{
+        PlayerSimulation, SimulationClock, SIMULATION,
+        SIMULATION_GAMEPLAY, TIMESTEP,
+    },
+    GameState,
+};
+
+/// Past this many projectiles alive at once, new ones
+/// aren't fired
+pub const MAX_PROJECTILES: usize = 256;
+
+const PROJECTILE_TEXTURE: &str = "particles/magic_01.png";
+const PROJECTILE_RADIUS: f32 = 4.;
+/// How hard a projectile pushes what it hits, along
+/// its flight, in px/s
+const PROJECTILE_KNOCKBACK: f32 = 150.;
+
+/// Things flying through levels and hurting what
+/// they hit, and the turrets firing them
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProjectilePool>()
+            .add_event::<FireProjectile>()
+            .add_system(
+                fire_turrets
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .before(ProjectileSystem::Fire),
+            )
+            .add_system(
+                fire_projectiles
+                    .run_in_state(GameState::Playing)
+                    .label(ProjectileSystem::Fire),
+            )
+            .add_fixed_timestep_system(
+                SIMULATION,
+                SIMULATION_GAMEPLAY,
+                fly_projectiles
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .after(PlayerSimulation),
+            );
+    }
+}
+
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+pub enum ProjectileSystem {
+    Fire,
+}
+
+/// Who a projectile can hurt
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Targets {
+    Players,
+    Enemies,
+}
+
+/// A projectile in flight. Stopped by walls, and by
+/// whatever it hits once its pierce runs out.
+#[derive(Clone, Debug, Component)]
+pub struct Projectile {
+    /// In px/s
+    pub velocity: Vec2,
+    /// In px/s²
+    pub gravity: f32,
+    pub lifetime: Duration,
+    pub damage: u32,
+    /// How many targets it goes through before it's
+    /// spent
+    pub pierce: u32,
+    pub owner: Entity,
+    pub targets: Targets,
+}
+
+/// Asks for a projectile to be fired from `position`,
+/// in world space. It belongs to `level`, and goes
+/// away when the level unloads.
+#[derive(Clone, Debug)]
+pub struct FireProjectile {
+    pub level: Entity,
+    pub position: Vec2,
+    pub projectile: Projectile,
+}
+
+/// How far along a pooled projectile is
+#[derive(Clone, Debug, Component)]
+struct Flight {
+    alive: bool,
+    until: Duration,
+    /// Where it is, in world space. Its
+    /// `GlobalTransform` is only brought up to date
+    /// after the simulation steps, so it can't be
+    /// trusted between steps.
+    position: Vec2,
+    /// Everything already hit, which it can't hit
+    /// again
+    hit: HashSet<Entity>,
+}
+
+/// Spent projectiles, hidden and waiting to be fired
+/// again
+#[derive(Debug, Default, Resource)]
+pub struct ProjectilePool {
+    free: Vec<Entity>,
+}
+
+/// The entity layer a turret was spawned in is a
+/// child of its level
+fn level_of(
+    entity: Entity,
+    parents: &Query<&Parent>,
+) -> Option<Entity> {
+    let layer = parents.get(entity).ok()?.get();
+    Some(parents.get(layer).ok()?.get())
+}
+
+fn fire_turrets(
+    clock: Res<SimulationClock>,
+    rapier_context: Res<RapierContext>,
+    mut turrets: Query<(
+        Entity,
+        &GlobalTransform,
+        &mut Turret,
+    )>,
+    parents: Query<&Parent>,
+    players: Query<&GlobalTransform, With<Player>>,
+    mut fire: EventWriter<FireProjectile>,
+) {
+    let now = clock.elapsed();
+    for (entity, transform, mut turret) in
+        turrets.iter_mut()
+    {
+        if now < turret.next_shot {
+            continue;
+        }
+        let position = transform.translation().truncate();
+        let direction = if turret.aim_at_player {
+            let target = players
+                .iter()
+                .map(|player| {
+                    player.translation().truncate()
+                })
+                .filter(|player| {
+                    player.distance(position)
+                        <= turret.range
+                        && line_of_sight(
+                            &rapier_context,
+                            position,
+                            *player,
+                        )
+                })
+                .min_by(|a, b| {
+                    a.distance(position)
+                        .total_cmp(&b.distance(position))
+                });
+            match target {
+                Some(target) => {
+                    (target - position).normalize_or_zero()
+                }
+                // keeps watching, and fires as soon as
+                // someone shows up
+                None => continue,
+            }
+        } else {
+            Vec2::from_angle(turret.angle.to_radians())
+        };
+        let level = match level_of(entity, &parents) {
+            Some(level) => level,
+            None => continue,
+        };
+
+        turret.next_shot =
+            now + Duration::from_secs_f32(turret.interval);
+        fire.send(FireProjectile {
+            level,
+            position,
+            projectile: Projectile {
+                velocity: direction * turret.speed,
+                gravity: turret.gravity,
+                lifetime: Duration::from_secs_f32(
+                    turret.lifetime,
+                ),
+                damage: turret.damage,
+                pierce: turret.pierce,
+                owner: entity,
+                targets: Targets::Players,
+            },
+        });
+    }
+}
+
+/// Fires projectiles from the pool, reusing spent
+/// ones. Each is parented to its level, so
+/// bevy_ecs_ldtk despawns it along with the level.
+fn fire_projectiles(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    clock: Res<SimulationClock>,
+    mut pool: ResMut<ProjectilePool>,
+    mut requests: EventReader<FireProjectile>,
+    projectiles: Query<(), With<Flight>>,
+    levels: Query<&GlobalTransform>,
+) {
+    // some were despawned along with their level
+    pool.free
+        .retain(|entity| projectiles.contains(*entity));
+    let mut total = projectiles.iter().count();
+
+    for request in requests.iter() {
+        let origin = match levels.get(request.level) {
+            Ok(level) => level.translation().truncate(),
+            Err(_) => continue,
+        };
+        let entity = match pool.free.pop() {
+            Some(entity) => entity,
+            None if total < MAX_PROJECTILES => {
+                total += 1;
+                commands.spawn_empty().id()
+            }
+            None => return,
+        };
+
+        commands.entity(request.level).add_child(entity);
+        commands.entity(entity).insert((
+            request.projectile.clone(),
+            Flight {
+                alive: true,
+                until: clock.elapsed()
+                    + request.projectile.lifetime,
+                position: request.position,
+                hit: HashSet::new(),
+            },
+            SpriteBundle {
+                texture: asset_server
+                    .load(PROJECTILE_TEXTURE),
+                sprite: Sprite {
+                    custom_size: Some(Vec2::splat(
+                        PROJECTILE_RADIUS * 4.,
+                    )),
+                    ..default()
+                },
+                // in front of the level, behind particles
+                transform: Transform::from_translation(
+                    (request.position - origin).extend(40.),
+                ),
+                ..default()
+            },
+        ));
+    }
+}
+
+/// Moves projectiles and damages what they touch.
+/// Walls are ray cast against along each step, so
+/// fast projectiles can't skip through them.
+fn fly_projectiles(
+    clock: Res<SimulationClock>,
+    rapier_context: Res<RapierContext>,
+    mut pool: ResMut<ProjectilePool>,
+    mut projectiles: Query<(
+        Entity,
+        &mut Projectile,
+        &mut Flight,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+    parents: Query<&Parent>,
+    healths: Query<(), With<Health>>,
+    players: Query<(), With<Player>>,
+    mut damage: EventWriter<DealDamage>,
+) {
+    let shape = Collider::ball(PROJECTILE_RADIUS);
+    for (
+        entity,
+        mut projectile,
+        mut flight,
+        mut transform,
+        mut visibility,
+    ) in projectiles.iter_mut()
+    {
+        if !flight.alive {
+            continue;
+        }
+
+        let gravity = projectile.gravity;
+        projectile.velocity.y -= gravity * TIMESTEP;
+        let step = projectile.velocity * TIMESTEP;
+        let from = flight.position;
+        let length = step.length();
+        let hit_wall = length > f32::EPSILON
+            && rapier_context
+                .cast_ray(
+                    from,
+                    step / length,
+                    length + PROJECTILE_RADIUS,
+                    true,
+                    QueryFilter::only_fixed()
+                        .exclude_sensors(),
+                )
+                .is_some();
+        let mut spent =
+            hit_wall || clock.elapsed() >= flight.until;
+
+        if !spent {
+            flight.position += step;
+            transform.translation += step.extend(0.);
+            let mut touching = Vec::new();
+            rapier_context.intersections_with_shape(
+                from + step,
+                0.,
+                &shape,
+                QueryFilter::exclude_fixed()
+                    .exclude_sensors(),
+                |collider| {
+                    touching.push(collider);
+                    true
+                },
+            );
+            for collider in touching {
+                // colliders can be parts of what they
+                // belong to
+                let target = if healths.contains(collider) {
+                    collider
+                } else {
+                    match parents.get(collider) {
+                        Ok(parent)
+                            if healths
+                                .contains(parent.get()) =>
+                        {
+                            parent.get()
+                        }
+                        _ => continue,
+                    }
+                };
+                let wanted = match projectile.targets {
+                    Targets::Players => {
+                        players.contains(target)
+                    }
+                    Targets::Enemies => {
+                        !players.contains(target)
+                    }
+                };
+                if !wanted
+                    || target == projectile.owner
+                    || !flight.hit.insert(target)
+                {
+                    continue;
+                }
+
+                damage.send(DealDamage {
+                    target,
+                    source: projectile.owner,
+                    amount: projectile.damage,
+                    kind: DamageKind::Projectile,
+                    knockback: projectile
+                        .velocity
+                        .normalize_or_zero()
+                        * PROJECTILE_KNOCKBACK,
+                });
+                if projectile.pierce == 0 {
+                    spent = true;
+                    break;
+                }
+                projectile.pierce -= 1;
+            }
+        }
+
+        if spent {
+            flight.alive = false;
+            visibility.is_visible = false;
+            pool.free.push(entity);
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::PlatformerAction,
+    bindings::InputProfiles,
+    camera::{gameplay_frozen, CameraLevel},
+    combat::{Invulnerable, Knockback},
+    components::{Player, PlayerSlot},
+    config,
+    gamepad::{slot_input_map, PlayerSlots},
+    movement::{
+        Dash, PlayerSimulation, PlayerState,
+        SimulationClock, SimulationInput, SIMULATION,
+        SIMULATION_GAMEPLAY, TIMESTEP,
+    },
+    GameState,
+};
+
+/// Bumped whenever recorded replays stop playing
+/// back the same way
+pub const REPLAY_VERSION: u32 = 1;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        // `--replay <file>` plays a replay as soon as
+        // the level is up, for bug reports
+        let mut args = std::env::args();
+        let pending = args
+            .position(|arg| arg == "--replay")
+            .and_then(|_| args.next())
+            .map(PathBuf::from);
+
+        app.insert_resource(ReplayState::default())
+            .insert_resource(PendingReplay(pending))
+            .add_system(
+                replay_controls
+                    .run_in_state(GameState::Playing),
+            )
+            .add_fixed_timestep_system(
+                SIMULATION,
+                SIMULATION_GAMEPLAY,
+                feed_inputs
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .label(ReplaySystem::Feed)
+                    .before(PlayerSimulation),
+            )
+            .add_fixed_timestep_system(
+                SIMULATION,
+                SIMULATION_GAMEPLAY,
+                record_inputs
+                    .run_in_state(GameState::Playing)
+                    .run_if_not(gameplay_frozen)
+                    .after(ReplaySystem::Feed)
+                    .before(PlayerSimulation),
+            );
+    }
+}
+
+#[derive(
+    SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash,
+)]
+enum ReplaySystem {
+    Feed,
+}
+
+/// Everything needed to play a session back: where
+/// it started, and every player's input on every
+/// simulation step
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub timestep: f32,
+    /// The LDtk iid of the level the replay starts in
+    pub level: String,
+    /// Where each player starts, by [`PlayerSlot`]
+    pub starts: Vec<Vec2>,
+    /// One entry per player per step
+    pub frames: Vec<Vec<FrameInput>>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| {
+                format!("reading {}", path.display())
+            })?;
+        let replay: Replay =
+            serde_json::from_str(&contents)?;
+        if replay.version != REPLAY_VERSION
+            || replay.timestep != TIMESTEP
+        {
+            anyhow::bail!(
+                "{} was recorded with a different version of the game",
+                path.display()
+            );
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        config::write_atomic(
+            path,
+            serde_json::to_string(self)?.as_bytes(),
+        )
+    }
+}
+
+/// The actions held down on one step, along with
+/// their values
+#[derive(
+    Clone, Debug, Default, PartialEq, Serialize, Deserialize,
+)]
+pub struct FrameInput(pub Vec<(PlatformerAction, f32)>);
+
+impl FrameInput {
+    pub fn capture(
+        action_state: &ActionState<PlatformerAction>,
+    ) -> Self {
+        Self(
+            action_state
+                .get_pressed()
+                .into_iter()
+                .map(|action| {
+                    (action, action_state.value(action))
+                })
+                .collect(),
+        )
+    }
+
+    /// Presses and releases actions to match this
+    /// step. `just_pressed` and `just_released` work
+    /// as they did while recording.
+    pub fn apply(
+        &self,
+        action_state: &mut ActionState<PlatformerAction>,
+    ) {
+        for action in PlatformerAction::variants() {
+            match self
+                .0
+                .iter()
+                .find(|(pressed, _)| *pressed == action)
+            {
+                Some((_, value)) => {
+                    action_state.press(action);
+                    action_state
+                        .action_data_mut(action)
+                        .value = *value;
+                }
+                None => action_state.release(action),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub enum ReplayState {
+    #[default]
+    Idle,
+    Recording(Replay),
+    Playing {
+        replay: Replay,
+        frame: usize,
+    },
+}
+
+#[derive(Debug, Default, Resource)]
+struct PendingReplay(Option<PathBuf>);
+
+fn replays_dir() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("replays"))
+}
+
+/// F5 starts and stops recording, F6 plays back the
+/// last recording
+fn replay_controls(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut state: ResMut<ReplayState>,
+    mut pending: ResMut<PendingReplay>,
+    camera_level: Res<CameraLevel>,
+    mut clock: ResMut<SimulationClock>,
+    mut players: Query<
+        (
+            Entity,
+            &PlayerSlot,
+            &mut Transform,
+            &mut KinematicCharacterController,
+        ),
+        With<Player>,
+    >,
+) {
+    let level = match camera_level.iid() {
+        Some(level) => level.to_string(),
+        // wait for the level to be up
+        None => return,
+    };
+
+    let mut to_play = pending.0.take();
+    if keys.just_pressed(KeyCode::F6) {
+        to_play = replays_dir()
+            .and_then(|dir| latest_replay(&dir).ok());
+    }
+
+    if keys.just_pressed(KeyCode::F5) {
+        match std::mem::take(&mut *state) {
+            ReplayState::Recording(replay) => {
+                save_recording(&replay);
+            }
+            ReplayState::Idle => {
+                let mut starts: Vec<(usize, Vec2)> =
+                    players
+                        .iter()
+                        .map(|(_, slot, transform, ..)| {
+                            (
+                                slot.0,
+                                transform
+                                    .translation
+                                    .truncate(),
+                            )
+                        })
+                        .collect();
+                starts.sort_by_key(|(slot, _)| *slot);
+                // playback starts from a clean slate, so
+                // recording has to as well
+                clock.steps = 0;
+                for (entity, .., mut controller) in
+                    players.iter_mut()
+                {
+                    restart_player(
+                        commands.entity(entity),
+                        &mut controller,
+                    );
+                }
+                *state = ReplayState::Recording(Replay {
+                    version: REPLAY_VERSION,
+                    timestep: TIMESTEP,
+                    level,
+                    starts: starts
+                        .into_iter()
+                        .map(|(_, start)| start)
+                        .collect(),
+                    frames: vec![],
+                });
+                info!("recording inputs");
+            }
+            playing => *state = playing,
+        }
+        return;
+    }
+
+    let path = match to_play {
+        Some(path) => path,
+        None => return,
+    };
+    let replay = match Replay::load(&path) {
+        Ok(replay) => replay,
+        Err(error) => {
+            error!("couldn't load replay: {error:?}");
+            return;
+        }
+    };
+    info!("playing back {}", path.display());
+
+    // put everyone back where the recording started,
+    // and take live input away from them
+    commands.insert_resource(LevelSelection::Iid(
+        replay.level.clone(),
+    ));
+    clock.steps = 0;
+    for (entity, slot, mut transform, mut controller) in
+        players.iter_mut()
+    {
+        if let Some(start) = replay.starts.get(slot.0) {
+            transform.translation.x = start.x;
+            transform.translation.y = start.y;
+        }
+        let mut player = commands.entity(entity);
+        player.remove::<InputMap<PlatformerAction>>();
+        restart_player(player, &mut controller);
+    }
+    *state = ReplayState::Playing { replay, frame: 0 };
+}
+
+/// Puts a player's simulation back to how it was
+/// when the [`SimulationClock`] was at zero. Anything
+/// timed on the clock has to go too, or it would
+/// wait for a time that's now far in the future.
+fn restart_player(
+    mut player: EntityCommands,
+    controller: &mut KinematicCharacterController,
+) {
+    player
+        .insert((PlayerState::default(), Dash::default()))
+        .remove::<(Invulnerable, Knockback)>();
+    controller.translation = None;
+}
+
+fn record_inputs(
+    mut state: ResMut<ReplayState>,
+    players: Query<(&PlayerSlot, &SimulationInput)>,
+) {
+    if let ReplayState::Recording(replay) = &mut *state {
+        let mut frame: Vec<(usize, FrameInput)> = players
+            .iter()
+            .map(|(slot, input)| {
+                (slot.0, FrameInput::capture(input))
+            })
+            .collect();
+        frame.sort_by_key(|(slot, _)| *slot);
+        replay.frames.push(
+            frame
+                .into_iter()
+                .map(|(_, input)| input)
+                .collect(),
+        );
+    }
+}
+
+/// Plays the recorded inputs back in place of live
+/// input, and hands control back once they run out.
+/// Systems outside the simulation see them too, on
+/// the frame of the step.
+fn feed_inputs(
+    mut commands: Commands,
+    mut state: ResMut<ReplayState>,
+    slots: Res<PlayerSlots>,
+    profiles: Res<InputProfiles>,
+    mut players: Query<(
+        Entity,
+        &PlayerSlot,
+        &mut SimulationInput,
+        &mut ActionState<PlatformerAction>,
+    )>,
+) {
+    let finished = match &mut *state {
+        ReplayState::Playing { replay, frame } => {
+            match replay.frames.get(*frame) {
+                Some(inputs) => {
+                    for (
+                        _,
+                        slot,
+                        mut input,
+                        mut action_state,
+                    ) in players.iter_mut()
+                    {
+                        if let Some(frame_input) =
+                            inputs.get(slot.0)
+                        {
+                            frame_input.apply(&mut input);
+                            frame_input
+                                .apply(&mut action_state);
+                        }
+                    }
+                    *frame += 1;
+                    false
+                }
+                None => true,
+            }
+        }
+        _ => false,
+    };
+
+    if finished {
+        info!("replay finished");
+        *state = ReplayState::Idle;
+        for (entity, slot, mut input, mut action_state) in
+            players.iter_mut()
+        {
+            input.release_all();
+            action_state.release_all();
+            commands.entity(entity).insert(slot_input_map(
+                &slots, &profiles, *slot,
+            ));
+        }
+    }
+}
+
+fn save_recording(replay: &Replay) {
+    let dir = match replays_dir() {
+        Some(dir) => dir,
+        None => {
+            error!(
+                "no data directory to save the replay in"
+            );
+            return;
+        }
+    };
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = dir.join(format!("{seconds}.json"));
+    match replay.save(&path) {
+        Ok(()) => info!(
+            "saved {} frames to {}",
+            replay.frames.len(),
+            path.display()
+        ),
+        Err(error) => {
+            error!("couldn't save replay: {error:?}")
+        }
+    }
+}
+
+fn latest_replay(dir: &Path) -> anyhow::Result<PathBuf> {
+    fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "json")
+        })
+        .max()
+        .context("no replays recorded yet")
+}
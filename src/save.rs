@@ -0,0 +1,497 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    camera::CameraLevel,
+    checkpoint::{CheckpointReached, ReachedCheckpoints},
+    components::{
+        Checkpoint, Equipment, Health, Items, Player,
+        PlayerSlot,
+    },
+    config,
+    items::CollectedPickups,
+    menu::{
+        spawn_menu, MenuBack, MenuConfirmed, MenuSystem,
+    },
+    GameState,
+};
+
+pub const SAVE_SLOTS: usize = 3;
+
+/// Bumped whenever [`SaveData`] changes in a way
+/// `#[serde(default)]` can't read older saves
+/// through, like a field being renamed or reshaped.
+/// Each bump adds a step to [`MIGRATIONS`].
+pub const SAVE_VERSION: u32 = 1;
+
+/// Upgrades a save's json from one version to the
+/// next. `MIGRATIONS[0]` takes version 1 to 2, and
+/// so on.
+const MIGRATIONS: [fn(&mut Map<String, Value>);
+    SAVE_VERSION as usize - 1] = [];
+
+/// Save slots, autosaved at every checkpoint and
+/// picked from the main menu
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveSlot>()
+            .init_resource::<PlayTime>()
+            .init_resource::<LastCheckpoint>()
+            .init_resource::<PendingLoad>()
+            .add_system(
+                count_play_time
+                    .run_in_state(GameState::Playing),
+            )
+            .add_system(
+                autosave.run_in_state(GameState::Playing),
+            )
+            // by now the level and everyone in it has
+            // spawned
+            .add_enter_system(
+                GameState::Playing,
+                restore_save,
+            )
+            .add_system(
+                save_slots_menu_actions
+                    .after(MenuSystem::Navigate),
+            );
+    }
+}
+
+/// Everything about a run that outlives it
+#[derive(
+    Clone, Debug, Default, PartialEq, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct SaveData {
+    pub version: u32,
+    /// The LDtk iid of the level the players were in
+    pub level: String,
+    /// The LDtk iid of the last checkpoint reached,
+    /// where players start when the save is loaded
+    pub checkpoint: Option<String>,
+    pub reached_checkpoints: BTreeSet<String>,
+    /// The iids of collected pickups, by the iid of
+    /// the level they were in
+    pub pickups: BTreeMap<String, BTreeSet<String>>,
+    /// One per player, by [`PlayerSlot`]
+    pub players: Vec<PlayerSave>,
+    /// In seconds
+    pub play_time: f64,
+}
+
+#[derive(
+    Clone, Debug, Default, PartialEq, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct PlayerSave {
+    pub health: Health,
+    pub items: Items,
+    pub equipment: Equipment,
+}
+
+impl SaveData {
+    /// Reads a save written by this or any earlier
+    /// version of the game
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let mut value: Value = serde_json::from_str(json)?;
+        let fields = value
+            .as_object_mut()
+            .context("a save should be a json object")?;
+        let version = fields
+            .get("version")
+            .and_then(Value::as_u64)
+            .context("the save has no version")?
+            as u32;
+        if version == 0 || version > SAVE_VERSION {
+            anyhow::bail!(
+                "save version {version} isn't one this \
+                 game knows, the newest is {SAVE_VERSION}"
+            );
+        }
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            migrate(fields);
+        }
+        fields.insert(
+            "version".to_string(),
+            SAVE_VERSION.into(),
+        );
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// The save in `slot`, or `None` if it's empty
+    pub fn load(
+        slot: usize,
+    ) -> anyhow::Result<Option<Self>> {
+        let path = slot_path(slot)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| {
+                format!("reading {}", path.display())
+            })?;
+        Self::from_json(&contents)
+            .with_context(|| {
+                format!("parsing {}", path.display())
+            })
+            .map(Some)
+    }
+
+    pub fn save(&self, slot: usize) -> anyhow::Result<()> {
+        config::write_atomic(
+            &slot_path(slot)?,
+            self.to_json()?.as_bytes(),
+        )
+    }
+}
+
+fn slot_path(slot: usize) -> anyhow::Result<PathBuf> {
+    Ok(config::data_dir()
+        .context("no data directory")?
+        .join("saves")
+        .join(format!("slot_{}.json", slot + 1)))
+}
+
+/// The slot the current run autosaves to. Runs
+/// started from the level select don't save.
+#[derive(Debug, Default, Resource)]
+pub struct ActiveSlot(pub Option<usize>);
+
+/// How long the current run has been played, in
+/// seconds
+#[derive(Debug, Default, Resource)]
+pub struct PlayTime(pub f64);
+
+/// The LDtk iid of the checkpoint last reached
+#[derive(Debug, Default, Resource)]
+pub struct LastCheckpoint(pub Option<String>);
+
+/// A save waiting for the world to spawn, to be put
+/// onto its players
+#[derive(Debug, Default, Resource)]
+struct PendingLoad(Option<SaveData>);
+
+fn count_play_time(
+    time: Res<Time>,
+    mut play_time: ResMut<PlayTime>,
+) {
+    play_time.0 += time.delta_seconds_f64();
+}
+
+/// The level iid of every entity in the project, to
+/// group pickups by level
+fn entity_levels(
+    ldtk_assets: &Assets<LdtkAsset>,
+) -> HashMap<String, String> {
+    ldtk_assets
+        .iter()
+        .flat_map(|(_, ldtk)| ldtk.iter_levels())
+        .flat_map(|level| {
+            level
+                .layer_instances
+                .iter()
+                .flatten()
+                .flat_map(|layer| &layer.entity_instances)
+                .map(move |entity| {
+                    (entity.iid.clone(), level.iid.clone())
+                })
+        })
+        .collect()
+}
+
+fn autosave(
+    slot: Res<ActiveSlot>,
+    mut reached: EventReader<CheckpointReached>,
+    mut last_checkpoint: ResMut<LastCheckpoint>,
+    camera_level: Res<CameraLevel>,
+    checkpoints: Res<ReachedCheckpoints>,
+    collected: Res<CollectedPickups>,
+    play_time: Res<PlayTime>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    players: Query<
+        (&PlayerSlot, &Health, &Items, &Equipment),
+        With<Player>,
+    >,
+) {
+    let event = match reached.iter().last() {
+        Some(event) => event,
+        None => return,
+    };
+    last_checkpoint.0 = Some(event.iid.clone());
+    let slot = match slot.0 {
+        Some(slot) => slot,
+        None => return,
+    };
+    let level = match camera_level.iid() {
+        Some(level) => level.to_string(),
+        None => return,
+    };
+
+    let levels = entity_levels(&ldtk_assets);
+    let mut pickups = BTreeMap::<_, BTreeSet<_>>::new();
+    for iid in collected.0.iter() {
+        let level =
+            levels.get(iid).cloned().unwrap_or_default();
+        pickups
+            .entry(level)
+            .or_default()
+            .insert(iid.clone());
+    }
+    let mut players: Vec<_> = players.iter().collect();
+    players.sort_by_key(|(slot, ..)| slot.0);
+
+    let save = SaveData {
+        version: SAVE_VERSION,
+        level,
+        checkpoint: last_checkpoint.0.clone(),
+        reached_checkpoints: checkpoints
+            .0
+            .iter()
+            .cloned()
+            .collect(),
+        pickups,
+        players: players
+            .into_iter()
+            .map(|(_, health, items, equipment)| {
+                PlayerSave {
+                    health: *health,
+                    items: items.clone(),
+                    equipment: equipment.clone(),
+                }
+            })
+            .collect(),
+        play_time: play_time.0,
+    };
+    match save.save(slot) {
+        Ok(()) => info!("saved to slot {}", slot + 1),
+        Err(error) => {
+            error!("couldn't save: {error:?}")
+        }
+    }
+}
+
+/// Puts a loaded save's players back the way they
+/// were, at the last checkpoint they reached
+fn restore_save(
+    mut pending: ResMut<PendingLoad>,
+    mut players: Query<
+        (
+            &PlayerSlot,
+            &mut Transform,
+            &GlobalTransform,
+            &mut Health,
+            &mut Items,
+            &mut Equipment,
+        ),
+        With<Player>,
+    >,
+    checkpoints: Query<
+        (&EntityInstance, &GlobalTransform),
+        With<Checkpoint>,
+    >,
+) {
+    let save = match pending.0.take() {
+        Some(save) => save,
+        None => return,
+    };
+    let start = save.checkpoint.as_ref().and_then(|iid| {
+        checkpoints
+            .iter()
+            .find(|(instance, _)| instance.iid == *iid)
+            .map(|(_, transform)| transform.translation())
+    });
+    for (
+        slot,
+        mut transform,
+        global,
+        mut health,
+        mut items,
+        mut equipment,
+    ) in players.iter_mut()
+    {
+        if let Some(player) = save.players.get(slot.0) {
+            *health = player.health;
+            *items = player.items.clone();
+            *equipment = player.equipment.clone();
+        }
+        // players are children of their level's
+        // entity layer, so move them by the offset
+        if let Some(start) = start {
+            let offset = start - global.translation();
+            transform.translation.x += offset.x;
+            transform.translation.y += offset.y;
+        }
+    }
+}
+
+/// A slot in the save slots menu
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+struct SlotItem(usize);
+
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct SaveSlotsMenu;
+
+/// How a slot is listed, like "Slot 1: Level 0,
+/// 1:05"
+fn describe_slot(
+    slot: usize,
+    ldtk_assets: &Assets<LdtkAsset>,
+) -> String {
+    let save = match SaveData::load(slot) {
+        Ok(Some(save)) => save,
+        Ok(None) => {
+            return format!("Slot {}: new game", slot + 1)
+        }
+        Err(error) => {
+            warn!("{error:?}");
+            return format!(
+                "Slot {}: unreadable",
+                slot + 1
+            );
+        }
+    };
+    let level = ldtk_assets
+        .iter()
+        .flat_map(|(_, ldtk)| ldtk.iter_levels())
+        .find(|level| level.iid == save.level)
+        .map_or(save.level.clone(), |level| {
+            level.identifier.replace('_', " ")
+        });
+    let minutes = (save.play_time / 60.) as u64;
+    // hours and minutes
+    format!(
+        "Slot {}: {level}, {}:{:02}",
+        slot + 1,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Opens the list of save slots to start or
+/// continue a run from
+pub fn spawn_save_slots_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    ldtk_assets: &Assets<LdtkAsset>,
+    depth: i32,
+) -> Entity {
+    spawn_menu(
+        commands,
+        asset_server,
+        "Play",
+        depth,
+        SaveSlotsMenu,
+        |menu| {
+            for slot in 0..SAVE_SLOTS {
+                menu.item(
+                    describe_slot(slot, ldtk_assets),
+                    SlotItem(slot),
+                );
+            }
+        },
+    )
+}
+
+/// Sets up the run saved in `slot` to start, or a
+/// new run there if it's empty
+pub fn load_slot(
+    commands: &mut Commands,
+    slot: usize,
+) -> anyhow::Result<()> {
+    let save = SaveData::load(slot)?;
+
+    commands.insert_resource(ActiveSlot(Some(slot)));
+    match save {
+        Some(save) => {
+            info!("loading slot {}", slot + 1);
+            commands.insert_resource(LevelSelection::Iid(
+                save.level.clone(),
+            ));
+            // set before the level spawns, so
+            // collected pickups never show up
+            commands.insert_resource(ReachedCheckpoints(
+                save.reached_checkpoints
+                    .iter()
+                    .cloned()
+                    .collect(),
+            ));
+            commands.insert_resource(CollectedPickups(
+                save.pickups
+                    .values()
+                    .flatten()
+                    .cloned()
+                    .collect(),
+            ));
+            commands
+                .insert_resource(PlayTime(save.play_time));
+            commands.insert_resource(LastCheckpoint(
+                save.checkpoint.clone(),
+            ));
+            commands
+                .insert_resource(PendingLoad(Some(save)));
+        }
+        None => {
+            commands
+                .insert_resource(LevelSelection::Index(0));
+            commands.insert_resource(
+                ReachedCheckpoints::default(),
+            );
+            commands.insert_resource(
+                CollectedPickups::default(),
+            );
+            commands.insert_resource(PlayTime::default());
+            commands
+                .insert_resource(LastCheckpoint::default());
+            commands
+                .insert_resource(PendingLoad::default());
+        }
+    }
+    Ok(())
+}
+
+/// Continues the run saved in a slot, or starts a
+/// new one there
+fn save_slots_menu_actions(
+    mut commands: Commands,
+    mut confirmed: EventReader<MenuConfirmed>,
+    mut back: EventReader<MenuBack>,
+    items: Query<&SlotItem>,
+    menus: Query<(), With<SaveSlotsMenu>>,
+) {
+    for MenuConfirmed(item) in confirmed.iter() {
+        let slot = match items.get(*item) {
+            Ok(SlotItem(slot)) => *slot,
+            Err(_) => continue,
+        };
+        if let Err(error) = load_slot(&mut commands, slot) {
+            error!("couldn't load: {error:?}");
+            continue;
+        }
+        commands
+            .insert_resource(NextState(GameState::Joining));
+    }
+    for MenuBack(menu) in back.iter() {
+        if menus.contains(*menu) {
+            commands.entity(*menu).despawn_recursive();
+        }
+    }
+}
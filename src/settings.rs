@@ -0,0 +1,558 @@
+use bevy::{
+    prelude::*,
+    window::{PresentMode, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bindings::{spawn_controls_menu, InputProfiles},
+    camera_shake::CameraShakeSettings,
+    config,
+    menu::{
+        spawn_menu, Menu, MenuBack, MenuConfirmed,
+        MenuSystem,
+    },
+    pixel_perfect::PixelPerfectSettings,
+};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// The window sizes the settings menu cycles through
+const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+/// How much a volume changes each time it's picked
+/// in the settings menu, wrapping back to silent
+/// past full
+const VOLUME_STEP: f32 = 0.1;
+/// The game speeds the settings menu cycles through
+const GAME_SPEEDS: [f32; 6] = [0.5, 0.6, 0.7, 0.8, 0.9, 1.];
+
+/// The player's settings, kept in the config
+/// directory apart from any save slot, and the
+/// settings menu to change them from
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Settings>()
+            .init_resource::<GameSpeed>()
+            .add_system(apply_video_settings)
+            .add_system(apply_accessibility_settings)
+            .add_system(store_bindings)
+            .add_system(
+                save_settings
+                    .after(store_bindings)
+                    .after(settings_menu_actions),
+            )
+            .add_system(
+                settings_menu_actions
+                    .after(MenuSystem::Navigate),
+            )
+            .add_system(
+                refresh_settings_menu
+                    .after(settings_menu_actions),
+            );
+    }
+}
+
+/// Everything in the settings file
+#[derive(
+    Clone, Debug, Default, Resource, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct Settings {
+    pub video: VideoSettings,
+    pub audio: AudioSettings,
+    pub accessibility: AccessibilitySettings,
+    pub bindings: InputProfiles,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub display_mode: DisplayMode,
+    /// The window's size when it isn't fullscreen,
+    /// in logical pixels
+    pub resolution: (u32, u32),
+    pub vsync: bool,
+    /// See [`PixelPerfectSettings`]
+    pub pixel_perfect: bool,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            display_mode: DisplayMode::Windowed,
+            resolution: RESOLUTIONS[0],
+            vsync: true,
+            pixel_perfect: false,
+        }
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl From<DisplayMode> for WindowMode {
+    fn from(mode: DisplayMode) -> Self {
+        match mode {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => {
+                WindowMode::BorderlessFullscreen
+            }
+            DisplayMode::Fullscreen => {
+                WindowMode::Fullscreen
+            }
+        }
+    }
+}
+
+/// Volumes from 0, silent, to 1. Anything playing
+/// sound should go through [`AudioSettings::music`]
+/// or [`AudioSettings::effects`].
+#[derive(
+    Clone, Copy, Debug, PartialEq, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub effects_volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 0.8,
+            music_volume: 0.8,
+            effects_volume: 1.,
+        }
+    }
+}
+
+impl AudioSettings {
+    /// How loud to play music
+    pub fn music(&self) -> f32 {
+        self.master_volume * self.music_volume
+    }
+
+    /// How loud to play sound effects
+    pub fn effects(&self) -> f32 {
+        self.master_volume * self.effects_volume
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    /// See [`CameraShakeSettings::enabled`]
+    pub screen_shake: bool,
+    /// How fast the game runs, from 0.5 to 1
+    pub game_speed: f32,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        Self {
+            screen_shake: true,
+            game_speed: 1.,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file, falling back to the
+    /// defaults. Called before the window is made.
+    pub fn load() -> Self {
+        match config::load::<Self>(SETTINGS_FILE) {
+            Ok(Some(mut settings)) => {
+                settings.bindings.bind_missing();
+                settings
+            }
+            // bindings used to have a file of their
+            // own
+            Ok(None) => Self {
+                bindings: InputProfiles::load(),
+                ..default()
+            },
+            Err(error) => {
+                warn!("couldn't load settings: {error:?}");
+                Self::default()
+            }
+        }
+    }
+
+    /// The window to open at startup
+    pub fn window_descriptor(&self) -> WindowDescriptor {
+        let (width, height) = self.video.resolution;
+        WindowDescriptor {
+            width: width as f32,
+            height: height as f32,
+            mode: self.video.display_mode.into(),
+            present_mode: present_mode(self.video.vsync),
+            ..default()
+        }
+    }
+}
+
+fn present_mode(vsync: bool) -> PresentMode {
+    if vsync {
+        PresentMode::AutoVsync
+    } else {
+        PresentMode::AutoNoVsync
+    }
+}
+
+/// Slows the game down for
+/// [`AccessibilitySettings::game_speed`].
+///
+/// `Time` is slowed to match, and the player
+/// simulation steps on it, see
+/// `movement::SIMULATION`, so every step stays a
+/// whole `movement::TIMESTEP` and they just come
+/// less often.
+#[derive(Debug, Resource)]
+pub struct GameSpeed {
+    pub speed: f32,
+}
+
+impl Default for GameSpeed {
+    fn default() -> Self {
+        Self { speed: 1. }
+    }
+}
+
+fn apply_video_settings(
+    settings: Res<Settings>,
+    mut windows: ResMut<Windows>,
+    mut pixel_perfect: ResMut<PixelPerfectSettings>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let video = &settings.video;
+    if pixel_perfect.enabled != video.pixel_perfect {
+        pixel_perfect.enabled = video.pixel_perfect;
+    }
+    // the window was made with these, see
+    // `Settings::window_descriptor`
+    if settings.is_added() {
+        return;
+    }
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+    let mode = video.display_mode.into();
+    if window.mode() != mode {
+        window.set_mode(mode);
+    }
+    let (width, height) = video.resolution;
+    if (window.requested_width(), window.requested_height())
+        != (width as f32, height as f32)
+    {
+        window.set_resolution(width as f32, height as f32);
+    }
+    let present_mode = present_mode(video.vsync);
+    if window.present_mode() != present_mode {
+        window.set_present_mode(present_mode);
+    }
+}
+
+fn apply_accessibility_settings(
+    settings: Res<Settings>,
+    mut time: ResMut<Time>,
+    mut game_speed: ResMut<GameSpeed>,
+    mut shake: ResMut<CameraShakeSettings>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let accessibility = &settings.accessibility;
+    shake.enabled = accessibility.screen_shake;
+    game_speed.speed =
+        accessibility.game_speed.clamp(0.5, 1.);
+    time.set_relative_speed(game_speed.speed);
+}
+
+/// Keeps the settings' copy of the bindings up to
+/// date with the controls menu's changes
+fn store_bindings(
+    profiles: Res<InputProfiles>,
+    mut settings: ResMut<Settings>,
+) {
+    if profiles.is_changed() && !profiles.is_added() {
+        settings.bindings = profiles.clone();
+    }
+}
+
+fn save_settings(settings: Res<Settings>) {
+    // the settings were just loaded, nothing new
+    // to write
+    if settings.is_added() || !settings.is_changed() {
+        return;
+    }
+    if let Err(error) =
+        config::save(SETTINGS_FILE, &*settings)
+    {
+        error!("couldn't save settings: {error:?}");
+    }
+}
+
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct SettingsMenu;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+enum SettingsItem {
+    DisplayMode,
+    Resolution,
+    Vsync,
+    PixelPerfect,
+    MasterVolume,
+    MusicVolume,
+    EffectsVolume,
+    ScreenShake,
+    GameSpeed,
+    Controls,
+    Back,
+}
+
+/// Opens the settings menu on top of whatever else
+/// is open
+pub fn spawn_settings_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    depth: i32,
+) -> Entity {
+    spawn_menu(
+        commands,
+        asset_server,
+        "Settings",
+        depth,
+        SettingsMenu,
+        |menu| {
+            use SettingsItem::*;
+
+            // labelled by `refresh_settings_menu`
+            for item in [
+                DisplayMode,
+                Resolution,
+                Vsync,
+                PixelPerfect,
+                MasterVolume,
+                MusicVolume,
+                EffectsVolume,
+                ScreenShake,
+                GameSpeed,
+            ] {
+                menu.item("", item);
+            }
+            menu.item("Controls", Controls);
+            menu.item("Back", Back);
+        },
+    )
+}
+
+/// The item after `current` in `options`, wrapping
+/// around to the first
+fn next_option<T: Copy + PartialEq>(
+    options: &[T],
+    current: T,
+) -> T {
+    let index = options
+        .iter()
+        .position(|option| *option == current)
+        .map_or(0, |index| (index + 1) % options.len());
+    options[index]
+}
+
+fn next_volume(volume: f32) -> f32 {
+    let next = (volume / VOLUME_STEP).round() + 1.;
+    if next * VOLUME_STEP > 1. + f32::EPSILON {
+        0.
+    } else {
+        next * VOLUME_STEP
+    }
+}
+
+fn settings_menu_actions(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut confirmed: EventReader<MenuConfirmed>,
+    mut back: EventReader<MenuBack>,
+    items: Query<(&SettingsItem, &Parent)>,
+    menus: Query<&Menu, With<SettingsMenu>>,
+    mut settings: ResMut<Settings>,
+) {
+    for MenuConfirmed(item) in confirmed.iter() {
+        let (item, parent) = match items.get(*item) {
+            Ok(item) => item,
+            Err(_) => continue,
+        };
+        let Settings {
+            video,
+            audio,
+            accessibility,
+            ..
+        } = &mut *settings;
+        match item {
+            SettingsItem::DisplayMode => {
+                video.display_mode = next_option(
+                    &[
+                        DisplayMode::Windowed,
+                        DisplayMode::Borderless,
+                        DisplayMode::Fullscreen,
+                    ],
+                    video.display_mode,
+                );
+            }
+            SettingsItem::Resolution => {
+                video.resolution = next_option(
+                    &RESOLUTIONS,
+                    video.resolution,
+                );
+            }
+            SettingsItem::Vsync => {
+                video.vsync = !video.vsync;
+            }
+            SettingsItem::PixelPerfect => {
+                video.pixel_perfect = !video.pixel_perfect;
+            }
+            SettingsItem::MasterVolume => {
+                audio.master_volume =
+                    next_volume(audio.master_volume);
+            }
+            SettingsItem::MusicVolume => {
+                audio.music_volume =
+                    next_volume(audio.music_volume);
+            }
+            SettingsItem::EffectsVolume => {
+                audio.effects_volume =
+                    next_volume(audio.effects_volume);
+            }
+            SettingsItem::ScreenShake => {
+                accessibility.screen_shake =
+                    !accessibility.screen_shake;
+            }
+            SettingsItem::GameSpeed => {
+                accessibility.game_speed = next_option(
+                    &GAME_SPEEDS,
+                    accessibility.game_speed,
+                );
+            }
+            SettingsItem::Controls => {
+                let depth = menus
+                    .get(parent.get())
+                    .map_or(0, |menu| menu.depth);
+                spawn_controls_menu(
+                    &mut commands,
+                    &asset_server,
+                    depth + 1,
+                );
+            }
+            SettingsItem::Back => {
+                commands
+                    .entity(parent.get())
+                    .despawn_recursive();
+            }
+        }
+    }
+    for MenuBack(menu) in back.iter() {
+        if menus.contains(*menu) {
+            commands.entity(*menu).despawn_recursive();
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+fn percent(value: f32) -> String {
+    format!("{}%", (value * 100.).round())
+}
+
+fn refresh_settings_menu(
+    settings: Res<Settings>,
+    mut items: Query<(&SettingsItem, &mut Text)>,
+) {
+    let Settings {
+        video,
+        audio,
+        accessibility,
+        ..
+    } = &*settings;
+    for (item, mut text) in items.iter_mut() {
+        let value = match item {
+            SettingsItem::DisplayMode => {
+                format!("Display: {:?}", video.display_mode)
+            }
+            SettingsItem::Resolution => {
+                let (width, height) = video.resolution;
+                format!("Resolution: {width}x{height}")
+            }
+            SettingsItem::Vsync => {
+                format!("VSync: {}", on_off(video.vsync))
+            }
+            SettingsItem::PixelPerfect => format!(
+                "Pixel perfect: {}",
+                on_off(video.pixel_perfect)
+            ),
+            SettingsItem::MasterVolume => format!(
+                "Master volume: {}",
+                percent(audio.master_volume)
+            ),
+            SettingsItem::MusicVolume => format!(
+                "Music volume: {}",
+                percent(audio.music_volume)
+            ),
+            SettingsItem::EffectsVolume => format!(
+                "Effects volume: {}",
+                percent(audio.effects_volume)
+            ),
+            SettingsItem::ScreenShake => format!(
+                "Screen shake: {}",
+                on_off(accessibility.screen_shake)
+            ),
+            SettingsItem::GameSpeed => format!(
+                "Game speed: {}",
+                percent(accessibility.game_speed)
+            ),
+            _ => continue,
+        };
+        // only touch the text when it changes, to keep
+        // the ui from relayouting every frame
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
@@ -1,57 +1,51 @@
-use crate::components::*;
-use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use crate::{
+    animation::{
+        Animator, FrameCollidersOn, PLAYER_ANIMATIONS,
+    },
+    components::*,
+    enemy::{EnemyBrain, State as EnemyState},
+    juice::{Juice, PlayerVisual},
+};
+use bevy::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 
 use std::collections::{HashMap, HashSet};
 
 use bevy_rapier2d::{prelude::*, rapier::prelude::Cuboid};
 
-// pub fn pause_physics_during_load(
-//     mut level_events: EventReader<LevelEvent>,
-//     mut physics_time: ResMut<PhysicsTime>,
-// ) {
-//     for event in level_events.iter() {
-//         match event {
-//             LevelEvent::SpawnTriggered(_) => {
-//                 physics_time.set_scale(0.)
-//             }
-//             LevelEvent::Transformed(_) => {
-//                 physics_time.set_scale(1.)
-//             }
-//             _ => (),
-//         }
-//     }
-// }
-
+/// Gives new players a sprite on a child entity, so
+/// its transform can be squashed and stretched
+/// without moving the physics body
 pub fn player_added(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    players: Query<
-        (Entity, &Transform),
+    asset_server: Res<AssetServer>,
+    mut players: Query<
+        (Entity, &mut KinematicCharacterController),
         (Added<EntityInstance>, With<Player>),
     >,
 ) {
-    let mesh = meshes.add(Mesh::from(shape::Capsule {
-        radius: 12.0,
-        depth: 24.0,
-        ..default()
-    }));
-    let material = materials.add(ColorMaterial::from(
-        Color::hex("1fa9f4").unwrap(),
-    ));
-    for (player, transform) in players.iter() {
-        commands.entity(player).insert_bundle(
-            MaterialMesh2dBundle {
-                mesh: mesh.clone().into(),
-                material: material.clone(),
-                transform: transform.clone(),
-                ..default()
-            },
-        );
-        // .insert(mesh.clone())
-        // .insert(material.clone())
-        // .insert(Visibility::visible());
+    let animations = asset_server.load(PLAYER_ANIMATIONS);
+    for (player, mut controller) in players.iter_mut() {
+        // other players' hit and hurt boxes, see
+        // `animation::FrameColliders`, shouldn't
+        // block anyone
+        controller.filter_flags =
+            QueryFilterFlags::EXCLUDE_SENSORS;
+        commands
+            .entity(player)
+            .insert(VisibilityBundle::default())
+            .with_children(|parent| {
+                parent.spawn((
+                    SpriteSheetBundle::default(),
+                    Animator::new(
+                        animations.clone(),
+                        "idle",
+                    ),
+                    FrameCollidersOn(player),
+                    PlayerVisual,
+                    Juice::default(),
+                ));
+            });
     }
 }
 
@@ -102,6 +96,44 @@ pub fn movement(
         }
     }
 }
+/// Where the walls added this frame are, storing
+/// them as GridCoords in a HashSet for quick, easy
+/// lookup
+///
+/// The key of this map will be the entity of the
+/// level the wall belongs to. This has two
+/// consequences in the resulting collision
+/// entities: 1. it forces the walls to be
+/// split along level boundaries 2. it lets us
+/// easily add the collision entities as children
+/// of the appropriate level entity
+pub fn walls_by_level(
+    wall_query: &Query<(&GridCoords, &Parent), Added<Wall>>,
+    parent_query: &Query<&Parent, Without<Wall>>,
+) -> HashMap<Entity, HashSet<GridCoords>> {
+    let mut level_to_wall_locations: HashMap<
+        Entity,
+        HashSet<GridCoords>,
+    > = HashMap::new();
+
+    wall_query.for_each(|(&grid_coords, parent)| {
+        // An intgrid tile's direct parent will be a layer
+        // entity, not the level entity To get the
+        // level entity, you need the tile's grandparent.
+        // This is where parent_query comes in.
+        if let Ok(grandparent) =
+            parent_query.get(parent.get())
+        {
+            level_to_wall_locations
+                .entry(grandparent.get())
+                .or_default()
+                .insert(grid_coords);
+        }
+    });
+
+    level_to_wall_locations
+}
+
 /// Spawns heron collisions for the walls of a
 /// level
 ///
@@ -151,36 +183,8 @@ pub fn spawn_wall_collision(
         bottom: i32,
     }
 
-    // Consider where the walls are
-    // storing them as GridCoords in a HashSet for
-    // quick, easy lookup
-    //
-    // The key of this map will be the entity of the
-    // level the wall belongs to. This has two
-    // consequences in the resulting collision
-    // entities: 1. it forces the walls to be
-    // split along level boundaries 2. it lets us
-    // easily add the collision entities as children
-    // of the appropriate level entity
-    let mut level_to_wall_locations: HashMap<
-        Entity,
-        HashSet<GridCoords>,
-    > = HashMap::new();
-
-    wall_query.for_each(|(&grid_coords, parent)| {
-        // An intgrid tile's direct parent will be a layer
-        // entity, not the level entity To get the
-        // level entity, you need the tile's grandparent.
-        // This is where parent_query comes in.
-        if let Ok(grandparent) =
-            parent_query.get(parent.get())
-        {
-            level_to_wall_locations
-                .entry(grandparent.get())
-                .or_insert(HashSet::new())
-                .insert(grid_coords);
-        }
-    });
+    let level_to_wall_locations =
+        walls_by_level(&wall_query, &parent_query);
 
     if !wall_query.is_empty() {
         level_query.for_each(|(level_entity, level_handle)| {
@@ -294,6 +298,7 @@ pub fn spawn_wall_collision(
                             GlobalTransform::default()));
                     }
                 });
+                commands.entity(level_entity).insert(WallColliders);
             }
         });
     }
@@ -304,21 +309,36 @@ pub fn patrol(
         &mut Transform,
         &mut Velocity,
         &mut Patrol,
+        Option<&EnemyStats>,
+        Option<&EnemyBrain>,
     )>,
 ) {
-    for (mut transform, mut velocity, mut patrol) in
-        query.iter_mut()
+    for (
+        mut transform,
+        mut velocity,
+        mut patrol,
+        stats,
+        brain,
+    ) in query.iter_mut()
     {
+        // busy chasing or the like
+        if brain.is_some_and(|brain| {
+            !matches!(brain.state(), EnemyState::Patrol {})
+        }) {
+            continue;
+        }
+        // nowhere to go, so stay put instead of drifting
         if patrol.points.len() <= 1 {
+            velocity.linvel = Vec2::ZERO;
             continue;
         }
+        let speed = stats.map_or(75., |stats| stats.speed);
 
-        let mut new_velocity = Vec2::from(
-            (patrol.points[patrol.index]
-                - transform.translation.truncate())
-            .normalize()
-                * 75.,
-        );
+        let target = patrol.points[patrol.index];
+        let mut new_velocity = (target
+            - transform.translation.truncate())
+        .normalize()
+            * speed;
 
         if new_velocity.dot(velocity.linvel) < 0. {
             if patrol.index == 0 {
@@ -343,100 +363,13 @@ pub fn patrol(
             new_velocity = (patrol.points[patrol.index]
                 - transform.translation.truncate())
             .normalize()
-                * 75.;
+                * speed;
         }
 
         velocity.linvel = new_velocity;
     }
 }
 
-const ASPECT_RATIO: f32 = 16. / 9.;
-
-pub fn camera_fit_inside_current_level(
-    mut camera_query: Query<
-        (
-            &mut bevy::render::camera::OrthographicProjection,
-            &mut Transform,
-        ),
-        Without<Player>,
-    >,
-    player_query: Query<&Transform, With<Player>>,
-    level_query: Query<
-        (&Transform, &Handle<LdtkLevel>),
-        (
-            Without<OrthographicProjection>,
-            Without<Player>,
-        ),
-    >,
-    level_selection: Res<LevelSelection>,
-    ldtk_levels: Res<Assets<LdtkLevel>>,
-) {
-    if let Ok(Transform {
-        translation: player_translation,
-        ..
-    }) = player_query.get_single()
-    {
-        let player_translation = *player_translation;
-
-        let (
-            mut orthographic_projection,
-            mut camera_transform,
-        ) = camera_query.single_mut();
-
-        for (level_transform, level_handle) in
-            level_query.iter()
-        {
-            if let Some(ldtk_level) =
-                ldtk_levels.get(level_handle)
-            {
-                let level = &ldtk_level.level;
-                if level_selection.is_match(&0, level) {
-                    let level_ratio = level.px_wid as f32
-                        / ldtk_level.level.px_hei as f32;
-
-                    orthographic_projection.scaling_mode = bevy::render::camera::ScalingMode::None;
-                    orthographic_projection.bottom = 0.;
-                    orthographic_projection.left = 0.;
-                    if level_ratio > ASPECT_RATIO {
-                        // level is wider than the screen
-                        orthographic_projection.top =
-                            (level.px_hei as f32 / 9.)
-                                .round()
-                                * 9.;
-                        orthographic_projection.right =
-                            orthographic_projection.top
-                                * ASPECT_RATIO;
-                        camera_transform.translation.x = (player_translation.x
-                            - level_transform.translation.x
-                            - orthographic_projection.right / 2.)
-                            .clamp(0., level.px_wid as f32 - orthographic_projection.right);
-                        camera_transform.translation.y = 0.;
-                    } else {
-                        // level is taller than the screen
-                        orthographic_projection.right =
-                            (level.px_wid as f32 / 16.)
-                                .round()
-                                * 16.;
-                        orthographic_projection.top =
-                            orthographic_projection.right
-                                / ASPECT_RATIO;
-                        camera_transform.translation.y = (player_translation.y
-                            - level_transform.translation.y
-                            - orthographic_projection.top / 2.)
-                            .clamp(0., level.px_hei as f32 - orthographic_projection.top);
-                        camera_transform.translation.x = 0.;
-                    }
-
-                    camera_transform.translation.x +=
-                        level_transform.translation.x;
-                    camera_transform.translation.y +=
-                        level_transform.translation.y;
-                }
-            }
-        }
-    }
-}
-
 pub fn update_level_selection(
     level_query: Query<
         (&Handle<LdtkLevel>, &Transform),
@@ -534,8 +467,17 @@ pub fn restart_level(
     input: Res<Input<KeyCode>>,
 ) {
     if input.just_pressed(KeyCode::R) {
-        for level_entity in level_query.iter() {
-            commands.entity(level_entity).insert(Respawn);
-        }
+        respawn_levels(&mut commands, &level_query);
+    }
+}
+
+/// Puts every loaded level back the way LDtk
+/// spawned it
+pub fn respawn_levels(
+    commands: &mut Commands,
+    level_query: &Query<Entity, With<Handle<LdtkLevel>>>,
+) {
+    for level_entity in level_query.iter() {
+        commands.entity(level_entity).insert(Respawn);
     }
 }
@@ -0,0 +1,334 @@
+use bevy::{app::AppExit, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    camera::{
+        CameraBounds, CameraLevel, LevelTransition,
+        MainCamera,
+    },
+    checkpoint::ReachedCheckpoints,
+    combat::HitStop,
+    components::{Health, Player},
+    items::CollectedPickups,
+    menu::{
+        spawn_menu, MenuBack, MenuConfirmed, MenuSystem,
+    },
+    pause::{freeze_gameplay, thaw_gameplay},
+    save::{load_slot, spawn_save_slots_menu, ActiveSlot},
+    settings::{spawn_settings_menu, GameSpeed},
+    GameState,
+};
+
+/// The main menu, level select and game over
+/// screens, and getting rid of the game world
+/// between runs
+pub struct TitlePlugin;
+
+impl Plugin for TitlePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_enter_system(
+            GameState::MainMenu,
+            despawn_gameplay,
+        )
+        .add_enter_system(GameState::MainMenu, forget_run)
+        .add_enter_system(
+            GameState::MainMenu,
+            spawn_main_menu,
+        )
+        .add_exit_system(
+            GameState::MainMenu,
+            despawn_screens,
+        )
+        .add_system(
+            main_menu_actions
+                .run_in_state(GameState::MainMenu)
+                .after(MenuSystem::Navigate),
+        )
+        .add_enter_system(
+            GameState::LevelSelect,
+            spawn_level_select,
+        )
+        .add_exit_system(
+            GameState::LevelSelect,
+            despawn_screens,
+        )
+        .add_system(
+            level_select_actions
+                .run_in_state(GameState::LevelSelect)
+                .after(MenuSystem::Navigate),
+        )
+        .add_system(
+            check_game_over
+                .run_in_state(GameState::Playing),
+        )
+        .add_enter_system(
+            GameState::GameOver,
+            freeze_gameplay,
+        )
+        .add_enter_system(
+            GameState::GameOver,
+            spawn_game_over,
+        )
+        .add_exit_system(GameState::GameOver, thaw_gameplay)
+        .add_exit_system(
+            GameState::GameOver,
+            despawn_screens,
+        )
+        // retrying loads the level over again
+        .add_exit_system(
+            GameState::GameOver,
+            despawn_gameplay,
+        )
+        .add_system(
+            game_over_actions
+                .run_in_state(GameState::GameOver)
+                .after(MenuSystem::Navigate),
+        );
+    }
+}
+
+/// Any of this module's menus
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Component,
+)]
+struct TitleScreen;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+enum MainMenuItem {
+    Play,
+    LevelSelect,
+    Settings,
+    Quit,
+}
+
+/// A level to start from, by iid
+#[derive(Clone, Debug, PartialEq, Eq, Component)]
+struct LevelItem(String);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+enum GameOverItem {
+    Retry,
+    MainMenu,
+}
+
+/// Despawns the world, and every player, level and
+/// projectile in it, along with the camera
+fn despawn_gameplay(
+    mut commands: Commands,
+    mut time: ResMut<Time>,
+    game_speed: Res<GameSpeed>,
+    worlds: Query<Entity, With<Handle<LdtkAsset>>>,
+    cameras: Query<Entity, With<MainCamera>>,
+) {
+    for entity in worlds.iter().chain(cameras.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<LevelTransition>();
+    commands.insert_resource(CameraLevel::default());
+    commands.insert_resource(CameraBounds::default());
+    commands.insert_resource(HitStop::default());
+    time.set_relative_speed(game_speed.speed);
+}
+
+/// Forgets what the last run reached and collected
+fn forget_run(mut commands: Commands) {
+    commands.insert_resource(ReachedCheckpoints::default());
+    commands.insert_resource(CollectedPickups::default());
+}
+
+fn despawn_screens(
+    mut commands: Commands,
+    screens: Query<Entity, With<TitleScreen>>,
+) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+fn spawn_main_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        "Platformer",
+        1,
+        TitleScreen,
+        |menu| {
+            menu.item("Play", MainMenuItem::Play);
+            menu.item(
+                "Level select",
+                MainMenuItem::LevelSelect,
+            );
+            menu.item("Settings", MainMenuItem::Settings);
+            menu.item("Quit", MainMenuItem::Quit);
+        },
+    );
+}
+
+fn main_menu_actions(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    mut confirmed: EventReader<MenuConfirmed>,
+    items: Query<&MainMenuItem>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for MenuConfirmed(item) in confirmed.iter() {
+        match items.get(*item) {
+            Ok(MainMenuItem::Play) => {
+                spawn_save_slots_menu(
+                    &mut commands,
+                    &asset_server,
+                    &ldtk_assets,
+                    2,
+                );
+            }
+            Ok(MainMenuItem::LevelSelect) => {
+                commands.insert_resource(NextState(
+                    GameState::LevelSelect,
+                ));
+            }
+            Ok(MainMenuItem::Settings) => {
+                spawn_settings_menu(
+                    &mut commands,
+                    &asset_server,
+                    2,
+                );
+            }
+            Ok(MainMenuItem::Quit) => exit.send(AppExit),
+            Err(_) => {}
+        }
+    }
+}
+
+fn spawn_level_select(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+) {
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        "Level select",
+        1,
+        TitleScreen,
+        |menu| {
+            for (_, ldtk) in ldtk_assets.iter() {
+                for level in ldtk.iter_levels() {
+                    menu.item(
+                        level.identifier.replace('_', " "),
+                        LevelItem(level.iid.clone()),
+                    );
+                }
+            }
+        },
+    );
+}
+
+fn level_select_actions(
+    mut commands: Commands,
+    mut confirmed: EventReader<MenuConfirmed>,
+    mut back: EventReader<MenuBack>,
+    items: Query<&LevelItem>,
+    screens: Query<(), With<TitleScreen>>,
+) {
+    for MenuConfirmed(item) in confirmed.iter() {
+        if let Ok(LevelItem(iid)) = items.get(*item) {
+            // practice runs don't touch the save slots
+            commands.insert_resource(ActiveSlot(None));
+            commands.insert_resource(LevelSelection::Iid(
+                iid.clone(),
+            ));
+            commands.insert_resource(NextState(
+                GameState::Joining,
+            ));
+        }
+    }
+    for MenuBack(menu) in back.iter() {
+        if screens.contains(*menu) {
+            commands.insert_resource(NextState(
+                GameState::MainMenu,
+            ));
+        }
+    }
+}
+
+/// The game is over once every player is out of
+/// health
+fn check_game_over(
+    mut commands: Commands,
+    players: Query<&Health, With<Player>>,
+) {
+    if !players.is_empty()
+        && players.iter().all(|health| health.current == 0)
+    {
+        commands.insert_resource(NextState(
+            GameState::GameOver,
+        ));
+    }
+}
+
+fn spawn_game_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        "Game over",
+        1,
+        TitleScreen,
+        |menu| {
+            menu.item("Retry", GameOverItem::Retry);
+            menu.item("Main menu", GameOverItem::MainMenu);
+        },
+    );
+}
+
+fn game_over_actions(
+    mut commands: Commands,
+    slot: Res<ActiveSlot>,
+    mut confirmed: EventReader<MenuConfirmed>,
+    items: Query<&GameOverItem>,
+) {
+    for MenuConfirmed(item) in confirmed.iter() {
+        let next = match items.get(*item) {
+            Ok(GameOverItem::Retry) => {
+                match slot.0 {
+                    // back to the last autosave, or the
+                    // next one would save over the slot
+                    // with a run that's lost its progress
+                    Some(slot) => {
+                        if let Err(error) =
+                            load_slot(&mut commands, slot)
+                        {
+                            error!(
+                                "couldn't load: {error:?}"
+                            );
+                            continue;
+                        }
+                    }
+                    // the level selection is kept, so the
+                    // same level loads again
+                    None => {
+                        commands.insert_resource(
+                            ReachedCheckpoints::default(),
+                        );
+                        commands.insert_resource(
+                            CollectedPickups::default(),
+                        );
+                    }
+                }
+                GameState::LevelLoading
+            }
+            Ok(GameOverItem::MainMenu) => {
+                GameState::MainMenu
+            }
+            Err(_) => continue,
+        };
+        commands.insert_resource(NextState(next));
+    }
+}
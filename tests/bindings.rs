@@ -0,0 +1,109 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use platformer::{
+    actions::PlatformerAction::{self, *},
+    bindings::rebind,
+};
+
+fn bound(
+    input_map: &InputMap<PlatformerAction>,
+    action: PlatformerAction,
+) -> Vec<UserInput> {
+    input_map.get(action).iter().cloned().collect()
+}
+
+#[test]
+fn unused_input_replaces_the_old_bindings() {
+    let mut input_map = InputMap::new([
+        (KeyCode::Space, Jump),
+        (KeyCode::W, Jump),
+        (KeyCode::J, Dash),
+    ]);
+    rebind(&mut input_map, Jump, KeyCode::K.into());
+
+    assert_eq!(
+        bound(&input_map, Jump),
+        [KeyCode::K.into()]
+    );
+    assert_eq!(
+        bound(&input_map, Dash),
+        [KeyCode::J.into()]
+    );
+}
+
+#[test]
+fn taken_input_swaps_with_the_other_action() {
+    let mut input_map = InputMap::new([
+        (KeyCode::Space, Jump),
+        (KeyCode::J, Dash),
+    ]);
+    rebind(&mut input_map, Jump, KeyCode::J.into());
+
+    assert_eq!(
+        bound(&input_map, Jump),
+        [KeyCode::J.into()]
+    );
+    assert_eq!(
+        bound(&input_map, Dash),
+        [KeyCode::Space.into()]
+    );
+}
+
+#[test]
+fn swapping_hands_over_every_old_binding() {
+    let mut input_map = InputMap::new([
+        (KeyCode::Space, Jump),
+        (KeyCode::W, Jump),
+        (KeyCode::J, Dash),
+    ]);
+    rebind(&mut input_map, Jump, KeyCode::J.into());
+
+    assert_eq!(
+        bound(&input_map, Jump),
+        [KeyCode::J.into()]
+    );
+    let dash = bound(&input_map, Dash);
+    assert_eq!(dash.len(), 2);
+    assert!(dash.contains(&KeyCode::Space.into()));
+    assert!(dash.contains(&KeyCode::W.into()));
+}
+
+#[test]
+fn rebinding_to_the_same_input_changes_nothing() {
+    let mut input_map = InputMap::new([
+        (KeyCode::Space, Jump),
+        (KeyCode::J, Dash),
+    ]);
+    rebind(&mut input_map, Jump, KeyCode::Space.into());
+
+    assert_eq!(
+        bound(&input_map, Jump),
+        [KeyCode::Space.into()]
+    );
+    assert_eq!(
+        bound(&input_map, Dash),
+        [KeyCode::J.into()]
+    );
+}
+
+#[test]
+fn gamepad_buttons_swap_too() {
+    let mut input_map = InputMap::new([
+        (GamepadButtonType::South, Jump),
+        (GamepadButtonType::West, Dash),
+    ]);
+    rebind(
+        &mut input_map,
+        Dash,
+        GamepadButtonType::South.into(),
+    );
+
+    assert_eq!(
+        bound(&input_map, Dash),
+        [GamepadButtonType::South.into()]
+    );
+    assert_eq!(
+        bound(&input_map, Jump),
+        [GamepadButtonType::West.into()]
+    );
+}
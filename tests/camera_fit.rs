@@ -0,0 +1,152 @@
+use bevy::{math::Rect, prelude::*};
+use platformer::camera::{
+    fit_inside_level, fit_viewport, FitMode,
+};
+
+const SIXTEEN_NINE: f32 = 16. / 9.;
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 0.01, "{a} != {b}");
+}
+
+fn level(width: f32, height: f32) -> Rect {
+    Rect {
+        min: Vec2::ZERO,
+        max: Vec2::new(width, height),
+    }
+}
+
+#[test]
+fn matching_window_has_no_bars() {
+    for mode in [
+        FitMode::Fit,
+        FitMode::Letterbox,
+        FitMode::Pillarbox,
+        FitMode::Expand,
+    ] {
+        assert_eq!(
+            fit_viewport(
+                UVec2::new(1920, 1080),
+                SIXTEEN_NINE,
+                mode
+            ),
+            (UVec2::ZERO, UVec2::new(1920, 1080))
+        );
+    }
+}
+
+#[test]
+fn ultra_wide_window_is_pillarboxed() {
+    let window = UVec2::new(2560, 1080);
+    let (position, size) =
+        fit_viewport(window, SIXTEEN_NINE, FitMode::Fit);
+    assert_eq!(size, UVec2::new(1920, 1080));
+    assert_eq!(position, UVec2::new(320, 0));
+
+    assert_eq!(
+        fit_viewport(
+            window,
+            SIXTEEN_NINE,
+            FitMode::Pillarbox
+        ),
+        (position, size)
+    );
+    // letterboxing only adds bars above and below
+    assert_eq!(
+        fit_viewport(
+            window,
+            SIXTEEN_NINE,
+            FitMode::Letterbox
+        ),
+        (UVec2::ZERO, window)
+    );
+}
+
+#[test]
+fn four_three_window_is_letterboxed() {
+    let window = UVec2::new(1024, 768);
+    let (position, size) =
+        fit_viewport(window, SIXTEEN_NINE, FitMode::Fit);
+    assert_eq!(size, UVec2::new(1024, 576));
+    assert_eq!(position, UVec2::new(0, 96));
+
+    assert_eq!(
+        fit_viewport(
+            window,
+            SIXTEEN_NINE,
+            FitMode::Letterbox
+        ),
+        (position, size)
+    );
+    assert_eq!(
+        fit_viewport(
+            window,
+            SIXTEEN_NINE,
+            FitMode::Pillarbox
+        ),
+        (UVec2::ZERO, window)
+    );
+}
+
+#[test]
+fn expand_uses_the_whole_window() {
+    let window = UVec2::new(800, 1200);
+    assert_eq!(
+        fit_viewport(window, SIXTEEN_NINE, FitMode::Expand),
+        (UVec2::ZERO, window)
+    );
+}
+
+#[test]
+fn wide_level_shows_full_height_and_follows_player() {
+    let level = level(1920., 1080.);
+
+    let rect = fit_inside_level(
+        level,
+        Vec2::new(960., 500.),
+        4. / 3.,
+    );
+    assert_close(rect.height(), 1080.);
+    assert_close(rect.width(), 1440.);
+    assert_close(rect.center().x, 960.);
+    assert_close(rect.min.y, 0.);
+}
+
+#[test]
+fn tall_level_shows_full_width_and_follows_player() {
+    let level = level(640., 2000.);
+
+    let rect = fit_inside_level(
+        level,
+        Vec2::new(100., 1000.),
+        SIXTEEN_NINE,
+    );
+    assert_close(rect.width(), 640.);
+    assert_close(rect.height(), 360.);
+    assert_close(rect.center().y, 1000.);
+    assert_close(rect.min.x, 0.);
+}
+
+#[test]
+fn view_is_clamped_to_level_edges() {
+    let level = Rect {
+        min: Vec2::new(1920., 320.),
+        max: Vec2::new(1920. + 3840., 320. + 1080.),
+    };
+
+    let left = fit_inside_level(
+        level,
+        Vec2::new(1930., 400.),
+        SIXTEEN_NINE,
+    );
+    assert_close(left.min.x, level.min.x);
+    assert_close(left.min.y, level.min.y);
+
+    let right = fit_inside_level(
+        level,
+        Vec2::new(level.max.x - 10., 400.),
+        21. / 9.,
+    );
+    assert_close(right.max.x, level.max.x);
+    assert_close(right.min.y, level.min.y);
+}
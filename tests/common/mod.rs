@@ -0,0 +1,215 @@
+//! A headless app running the real player
+//! simulation against a hand-built level, for
+//! stepping frame by frame in tests
+
+// Each test binary only uses some of the helpers
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use bevy::{
+    ecs::event::ManualEventReader, input::InputPlugin,
+    prelude::*, time::TimeUpdateStrategy,
+};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use iyes_loopless::prelude::*;
+use leafwing_input_manager::{
+    prelude::*, user_input::InputKind,
+};
+use platformer::{
+    actions::PlatformerAction,
+    bindings::{default_keyboard_map, InputProfiles},
+    camera::CameraLevel,
+    components::{PlayerBundle, PlayerSlot},
+    gamepad::PlayerSlots,
+    movement::{
+        Landed, MovementPlugin, PlayerState, State,
+        TIMESTEP,
+    },
+    replay::ReplayPlugin,
+    GameState,
+};
+
+/// Half the height of the player's capsule
+pub const PLAYER_HALF_HEIGHT: f32 = 24.;
+/// The radius of the player's capsule
+pub const PLAYER_RADIUS: f32 = 12.;
+
+pub struct Harness {
+    pub app: App,
+    pub player: Entity,
+    /// Every [`Landed`] event sent so far
+    pub landings: Vec<Landed>,
+    landed_reader: ManualEventReader<Landed>,
+    /// Where `Time` is up to. Tests move it by hand so
+    /// the simulation steps the same on every run.
+    now: Instant,
+}
+
+impl Harness {
+    /// A flat floor whose top is at `y = 0`, with a
+    /// player standing on it at `x = 0`
+    pub fn flat_floor() -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TransformPlugin)
+            .add_plugin(HierarchyPlugin)
+            .add_plugin(InputPlugin)
+            .add_plugin(InputManagerPlugin::<
+                PlatformerAction,
+            >::default())
+            .insert_resource(RapierConfiguration {
+                timestep_mode: TimestepMode::Fixed {
+                    dt: TIMESTEP,
+                    substeps: 1,
+                },
+                ..default()
+            })
+            .add_plugin(
+                RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(
+                    100.0,
+                )
+                .with_default_system_setup(false),
+            )
+            .add_loopless_state(GameState::Playing)
+            .add_plugin(MovementPlugin)
+            .init_resource::<CameraLevel>()
+            .init_resource::<PlayerSlots>()
+            .init_resource::<InputProfiles>()
+            .add_plugin(ReplayPlugin);
+
+        app.world.spawn((
+            Collider::cuboid(1000., 16.),
+            TransformBundle::from(Transform::from_xyz(
+                0., -16., 0.,
+            )),
+        ));
+
+        let player = app
+            .world
+            .spawn((
+                PlayerBundle::from_entity_instance(
+                    &EntityInstance {
+                        identifier: "Player".to_string(),
+                        ..default()
+                    },
+                ),
+                TransformBundle::from(Transform::from_xyz(
+                    0.,
+                    PLAYER_HALF_HEIGHT + 1.,
+                    0.,
+                )),
+            ))
+            .insert((default_keyboard_map(), PlayerSlot(0)))
+            .id();
+
+        let now = app.world.resource::<Time>().startup();
+        let mut harness = Self {
+            app,
+            player,
+            landings: vec![],
+            landed_reader: default(),
+            now,
+        };
+        // settle onto the floor
+        harness.step(10);
+        harness.landings.clear();
+        harness
+    }
+
+    /// A wall with its bottom on the floor
+    pub fn wall(&mut self, left: f32, width: f32) {
+        self.app.world.spawn((
+            Collider::cuboid(width / 2., 200.),
+            TransformBundle::from(Transform::from_xyz(
+                left + width / 2.,
+                200.,
+                0.,
+            )),
+        ));
+    }
+
+    /// Holds down the key bound to `action` in the
+    /// default keyboard profile
+    pub fn press(&mut self, action: PlatformerAction) {
+        let key = action_key(action);
+        self.app
+            .world
+            .resource_mut::<Input<KeyCode>>()
+            .press(key);
+    }
+
+    pub fn release(&mut self, action: PlatformerAction) {
+        let key = action_key(action);
+        self.app
+            .world
+            .resource_mut::<Input<KeyCode>>()
+            .release(key);
+    }
+
+    /// Runs `frames` frames, each one [`TIMESTEP`] of
+    /// simulation
+    pub fn step(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.frame(Duration::from_secs_f32(TIMESTEP));
+        }
+    }
+
+    /// Runs one frame `delta` after the last, for as
+    /// many simulation steps as fit
+    pub fn frame(&mut self, delta: Duration) {
+        self.now += delta;
+        self.app.insert_resource(
+            TimeUpdateStrategy::ManualInstant(self.now),
+        );
+        self.app.update();
+        let events =
+            self.app.world.resource::<Events<Landed>>();
+        self.landings.extend(
+            self.landed_reader.iter(events).copied(),
+        );
+    }
+
+    pub fn position(&self) -> Vec2 {
+        self.app
+            .world
+            .get::<Transform>(self.player)
+            .expect("the player has a transform")
+            .translation
+            .truncate()
+    }
+
+    pub fn state(&self) -> &State {
+        self.app
+            .world
+            .get::<PlayerState>(self.player)
+            .expect("the player has a state machine")
+            .state()
+    }
+
+    pub fn grounded(&self) -> bool {
+        self.app
+            .world
+            .get::<KinematicCharacterControllerOutput>(
+                self.player,
+            )
+            .is_some_and(|output| output.grounded)
+    }
+}
+
+fn action_key(action: PlatformerAction) -> KeyCode {
+    let input_map = default_keyboard_map();
+    let key =
+        input_map.get(action).iter().find_map(|input| {
+            match input {
+                UserInput::Single(InputKind::Keyboard(
+                    key,
+                )) => Some(*key),
+                _ => None,
+            }
+        });
+    key.unwrap_or_else(|| {
+        panic!("{action:?} has no key bound")
+    })
+}
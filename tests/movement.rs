@@ -0,0 +1,178 @@
+mod common;
+
+use common::{Harness, PLAYER_HALF_HEIGHT, PLAYER_RADIUS};
+use platformer::{
+    actions::PlatformerAction, movement::State,
+};
+
+/// Steps until the player is back on the ground,
+/// returning the highest point they reached
+fn step_until_grounded(harness: &mut Harness) -> f32 {
+    let mut peak = harness.position().y;
+    for _ in 0..120 {
+        harness.step(1);
+        peak = peak.max(harness.position().y);
+        if harness.grounded()
+            && matches!(harness.state(), State::Idle {})
+        {
+            return peak;
+        }
+    }
+    panic!("the player never landed");
+}
+
+#[test]
+fn player_settles_on_the_floor() {
+    let harness = Harness::flat_floor();
+    assert!(harness.grounded());
+    assert!(matches!(harness.state(), State::Idle {}));
+    assert!(
+        (harness.position().y - PLAYER_HALF_HEIGHT).abs()
+            < 1.,
+        "{:?}",
+        harness.position()
+    );
+}
+
+#[test]
+fn held_jump_rises_for_half_a_second() {
+    let mut harness = Harness::flat_floor();
+    let start = harness.position().y;
+
+    harness.press(PlatformerAction::Jump);
+    harness.step(1);
+    assert!(matches!(harness.state(), State::Jumping {}));
+
+    // 10px a frame for 500ms, and the peak is well
+    // behind by the time the player is falling
+    let mut peak = harness.position().y;
+    for _ in 0..40 {
+        harness.step(1);
+        peak = peak.max(harness.position().y);
+    }
+    assert!(matches!(harness.state(), State::Falling {}));
+    let peak = peak.max(step_until_grounded(&mut harness));
+    let height = peak - start;
+    assert!(
+        (280. ..=320.).contains(&height),
+        "jumped {height}px"
+    );
+}
+
+#[test]
+fn releasing_jump_cuts_it_short() {
+    let mut harness = Harness::flat_floor();
+    let start = harness.position().y;
+
+    harness.press(PlatformerAction::Jump);
+    harness.step(10);
+    harness.release(PlatformerAction::Jump);
+    harness.step(1);
+    assert!(matches!(harness.state(), State::Falling {}));
+
+    let peak = step_until_grounded(&mut harness);
+    let height = peak - start;
+    assert!(
+        (80. ..=120.).contains(&height),
+        "jumped {height}px"
+    );
+}
+
+#[test]
+fn landing_returns_to_idle() {
+    let mut harness = Harness::flat_floor();
+    let start = harness.position().y;
+
+    harness.press(PlatformerAction::Jump);
+    harness.step(1);
+    let peak = step_until_grounded(&mut harness);
+
+    assert!(harness.grounded());
+    assert!(matches!(harness.state(), State::Idle {}));
+    assert!(
+        (harness.position().y - start).abs() < 1.,
+        "{:?}",
+        harness.position()
+    );
+
+    assert_eq!(harness.landings.len(), 1);
+    let landed = harness.landings[0];
+    assert_eq!(landed.entity, harness.player);
+    // the fall is measured from where it started,
+    // which is a frame or so past the peak
+    assert!(
+        (landed.fall_height - (peak - start)).abs() < 25.,
+        "fell {} from a {}px jump",
+        landed.fall_height,
+        peak - start
+    );
+
+    // holding jump after landing doesn't jump again
+    harness.step(30);
+    assert!(matches!(harness.state(), State::Idle {}));
+}
+
+#[test]
+fn walls_stop_the_player() {
+    let mut harness = Harness::flat_floor();
+    harness.wall(200., 32.);
+
+    // 5px a frame would take the player well past
+    // the wall
+    harness.press(PlatformerAction::Right);
+    harness.step(120);
+
+    let x = harness.position().x;
+    assert!(x <= 200. - PLAYER_RADIUS + 0.5, "x = {x}");
+    assert!(x > 200. - PLAYER_RADIUS - 2., "x = {x}");
+    assert!(harness.grounded());
+    assert!(matches!(harness.state(), State::Idle {}));
+}
+
+#[test]
+fn walking_away_from_a_wall() {
+    let mut harness = Harness::flat_floor();
+    harness.wall(-232., 32.);
+
+    harness.press(PlatformerAction::Left);
+    harness.step(120);
+    let at_wall = harness.position().x;
+    assert!(at_wall >= -200. + PLAYER_RADIUS - 0.5);
+
+    harness.release(PlatformerAction::Left);
+    harness.press(PlatformerAction::Right);
+    harness.step(20);
+    assert!(
+        (harness.position().x - (at_wall + 100.)).abs()
+            < 1.,
+        "x = {}",
+        harness.position().x
+    );
+}
+
+#[test]
+fn dash_shoots_sideways_then_cools_down() {
+    let mut harness = Harness::flat_floor();
+    let start = harness.position().x;
+
+    // 15px a frame for 150ms, facing right
+    harness.press(PlatformerAction::Dash);
+    harness.step(9);
+    harness.release(PlatformerAction::Dash);
+    let dashed = harness.position().x - start;
+    assert!(
+        (130. ..=140.).contains(&dashed),
+        "dashed {dashed}px"
+    );
+
+    // pressing again during the cooldown does nothing
+    harness.step(1);
+    let x = harness.position().x;
+    harness.press(PlatformerAction::Dash);
+    harness.step(5);
+    assert!(
+        (harness.position().x - x).abs() < 1.,
+        "x = {}",
+        harness.position().x
+    );
+}
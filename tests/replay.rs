@@ -0,0 +1,106 @@
+mod common;
+
+use std::time::Duration;
+
+use common::Harness;
+use leafwing_input_manager::prelude::*;
+use platformer::{
+    actions::PlatformerAction::{self, *},
+    movement::TIMESTEP,
+    replay::{Replay, ReplayState},
+};
+
+/// Runs left and right, jumps and dashes, with every
+/// step recorded
+fn record() -> (Replay, Harness) {
+    let mut harness = Harness::flat_floor();
+    harness.app.insert_resource(ReplayState::Recording(
+        Replay::default(),
+    ));
+
+    harness.press(Right);
+    harness.step(10);
+    harness.press(Jump);
+    harness.step(12);
+    harness.release(Jump);
+    harness.press(Dash);
+    harness.step(3);
+    harness.release(Dash);
+    harness.release(Right);
+    harness.press(Left);
+    harness.step(25);
+    harness.press(Jump);
+    harness.step(40);
+    harness.release(Jump);
+    harness.release(Left);
+    harness.step(60);
+
+    let replay = match harness
+        .app
+        .world
+        .resource_mut::<ReplayState>()
+        .as_mut()
+    {
+        ReplayState::Recording(replay) => {
+            std::mem::take(replay)
+        }
+        state => panic!("stopped recording: {state:?}"),
+    };
+    (replay, harness)
+}
+
+/// Plays `replay` back with frames of the given
+/// lengths in milliseconds, over and over, until it
+/// runs out
+fn play_back(replay: Replay, frames: &[f32]) -> Harness {
+    let mut harness = Harness::flat_floor();
+    harness
+        .app
+        .world
+        .entity_mut(harness.player)
+        .remove::<InputMap<PlatformerAction>>();
+    harness.app.insert_resource(ReplayState::Playing {
+        replay,
+        frame: 0,
+    });
+
+    for millis in frames.iter().cycle() {
+        if matches!(
+            *harness.app.world.resource::<ReplayState>(),
+            ReplayState::Idle
+        ) {
+            break;
+        }
+        harness
+            .frame(Duration::from_secs_f32(millis / 1000.));
+    }
+    harness
+}
+
+#[test]
+fn replays_play_back_exactly() {
+    let (replay, recorded) = record();
+    assert_eq!(replay.frames.len(), 150);
+
+    let played = play_back(replay, &[TIMESTEP * 1000.]);
+    assert_eq!(played.position(), recorded.position());
+    assert_eq!(
+        played.landings.len(),
+        recorded.landings.len()
+    );
+}
+
+#[test]
+fn replays_ignore_the_frame_rate() {
+    let (replay, recorded) = record();
+
+    // a fast display, a slow one and a hitchy one
+    for frames in [&[7.][..], &[33.], &[4., 50., 0., 16.]] {
+        let played = play_back(replay.clone(), frames);
+        assert_eq!(
+            played.position(),
+            recorded.position(),
+            "with {frames:?}ms frames"
+        );
+    }
+}
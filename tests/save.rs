@@ -0,0 +1,59 @@
+use platformer::{
+    components::{Health, Items},
+    save::{PlayerSave, SaveData, SAVE_VERSION},
+};
+
+fn example() -> SaveData {
+    SaveData {
+        version: SAVE_VERSION,
+        level: "level-iid".to_string(),
+        checkpoint: Some("checkpoint-iid".to_string()),
+        reached_checkpoints: ["checkpoint-iid".to_string()]
+            .into(),
+        pickups: [(
+            "level-iid".to_string(),
+            ["pickup-iid".to_string()].into(),
+        )]
+        .into(),
+        players: vec![PlayerSave {
+            health: Health { current: 2, max: 5 },
+            items: Items::default(),
+            ..Default::default()
+        }],
+        play_time: 65.5,
+    }
+}
+
+#[test]
+fn round_trips() {
+    let save = example();
+    let json = save.to_json().unwrap();
+    assert_eq!(SaveData::from_json(&json).unwrap(), save);
+}
+
+#[test]
+fn missing_fields_get_defaults() {
+    let save = SaveData::from_json(
+        r#"{ "version": 1, "level": "a" }"#,
+    )
+    .unwrap();
+    assert_eq!(save.level, "a");
+    assert_eq!(save.version, SAVE_VERSION);
+    assert!(save.players.is_empty());
+    assert!(save.checkpoint.is_none());
+}
+
+#[test]
+fn rejects_unknown_versions() {
+    let newer = format!(
+        r#"{{ "version": {}, "level": "a" }}"#,
+        SAVE_VERSION + 1
+    );
+    assert!(SaveData::from_json(&newer).is_err());
+    assert!(
+        SaveData::from_json(r#"{ "version": 0 }"#).is_err()
+    );
+    assert!(
+        SaveData::from_json(r#"{ "level": "a" }"#).is_err()
+    );
+}